@@ -2,10 +2,19 @@
 
 in vec3 vertexColor;  // Color passed from the vertex shader
 out vec4 FragColor;   // Final color output
+uniform vec4 tint = vec4(1.0);
+uniform float lodFade = 0.0;  // LOD cross-fade, see DrawItem::fade
+
+const float bayer[16] = float[16](0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5);
 
 void main()
 {
+    ivec2 cell = ivec2(gl_FragCoord.xy) % 4;
+    float dither = (bayer[cell.y * 4 + cell.x] + 0.5) / 16.0;
+    if ((lodFade > 0.0 && dither >= lodFade) || (lodFade < 0.0 && dither < -lodFade))
+        discard;
+
     vec3 normalizedColor = vertexColor * 0.5 + 0.5;
 
-    FragColor = vec4(normalizedColor.r, normalizedColor.g * 0.7, normalizedColor.b * 0.9, 1.0);  // Use the passed color, with full opacity
+    FragColor = tint * vec4(normalizedColor.r, normalizedColor.g * 0.7, normalizedColor.b * 0.9, 1.0);  // Use the passed color, with full opacity
 }
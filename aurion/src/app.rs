@@ -1,20 +1,12 @@
 use crate::prelude::*;
+use crate::stats::{self, Phase};
+use crate::trace::trace_span;
 
-use sdl2::video::Window;
-
-#[repr(C)]
-pub struct Vertex {
-    pub position: Vec3,
-    pub normal: Vec3,
-    pub uv: Vec2,
-}
-pub enum TextureType {
-    DIFFUSE(i32),
-    SPECULAR(i32),
-}
-pub struct Texture {
-    pub texture_type: TextureType,
-}
+use sdl2::event::Event;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+use sdl2::video::{GLProfile, SwapInterval, Window};
+use std::time::Duration;
 
 pub struct App {
     video: VideoSubsystem,
@@ -25,31 +17,211 @@ pub struct App {
     pub window_height: u32,
 }
 
-impl App {
-    pub fn new(title: &str, window_width: u32, window_height: u32) -> Self {
-        let sdl = sdl2::init().unwrap();
-        let video = sdl.video().unwrap();
-        let gl_attr = video.gl_attr();
-        gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
-        gl_attr.set_context_version(3, 3);
-        gl_attr.set_context_flags().forward_compatible().set();
-        let window = video
-            .window(title, window_width, window_height)
-            .opengl()
-            .resizable()
-            .build()
-            .unwrap();
-        let gl_context = window
-            .gl_create_context()
-            .expect("Can't create OpenGL context!!!");
+#[derive(Debug)]
+pub enum AppError {
+    Sdl(String),
+    Window(sdl2::video::WindowBuildError),
+    Context(String),
+    Icon(String),
+}
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Sdl(e) => write!(f, "SDL initialization failed: {e}"),
+            AppError::Window(e) => write!(f, "window creation failed: {e}"),
+            AppError::Context(e) => write!(f, "OpenGL context creation failed: {e}"),
+            AppError::Icon(e) => write!(f, "invalid window icon: {e}"),
+        }
+    }
+}
+impl std::error::Error for AppError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WindowMode {
+    Windowed,
+    Fullscreen,
+    /// Fullscreen at desktop resolution, without a mode switch.
+    Borderless,
+}
+
+pub struct AppBuilder {
+    title: String,
+    width: u32,
+    height: u32,
+    gl_version: (u8, u8),
+    gl_profile: GLProfile,
+    msaa_samples: u8,
+    depth_bits: u8,
+    stencil_bits: u8,
+    debug_context: bool,
+    vsync: bool,
+    mode: WindowMode,
+    resizable: bool,
+    high_dpi: bool,
+    hidden: bool,
+    position: Option<(i32, i32)>,
+    icon: Option<(u32, u32, Vec<u8>)>,
+}
+impl AppBuilder {
+    pub fn new(title: &str, width: u32, height: u32) -> Self {
         Self {
+            title: title.to_string(),
+            width,
+            height,
+            gl_version: (3, 3),
+            gl_profile: GLProfile::Core,
+            msaa_samples: 0,
+            depth_bits: 24,
+            stencil_bits: 8,
+            debug_context: cfg!(debug_assertions),
+            vsync: true,
+            mode: WindowMode::Windowed,
+            resizable: true,
+            high_dpi: false,
+            hidden: false,
+            position: None,
+            icon: None,
+        }
+    }
+    pub fn gl_version(mut self, major: u8, minor: u8) -> Self {
+        self.gl_version = (major, minor);
+        self
+    }
+    pub fn gl_profile(mut self, profile: GLProfile) -> Self {
+        self.gl_profile = profile;
+        self
+    }
+    /// 0 disables multisampling.
+    pub fn msaa_samples(mut self, samples: u8) -> Self {
+        self.msaa_samples = samples;
+        self
+    }
+    pub fn depth_bits(mut self, bits: u8) -> Self {
+        self.depth_bits = bits;
+        self
+    }
+    pub fn stencil_bits(mut self, bits: u8) -> Self {
+        self.stencil_bits = bits;
+        self
+    }
+    /// Asks the driver for KHR_debug output, which aurion forwards to the log.
+    /// On by default in debug builds.
+    pub fn debug_context(mut self, debug: bool) -> Self {
+        self.debug_context = debug;
+        self
+    }
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = vsync;
+        self
+    }
+    pub fn window_mode(mut self, mode: WindowMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+    pub fn high_dpi(mut self, high_dpi: bool) -> Self {
+        self.high_dpi = high_dpi;
+        self
+    }
+    /// For tools that only need a GL context.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+    /// Centered when not set.
+    pub fn position(mut self, x: i32, y: i32) -> Self {
+        self.position = Some((x, y));
+        self
+    }
+    /// Tightly packed RGBA8 pixels.
+    pub fn icon(mut self, width: u32, height: u32, rgba: Vec<u8>) -> Self {
+        self.icon = Some((width, height, rgba));
+        self
+    }
+    pub fn build(self) -> Result<App, AppError> {
+        let sdl = sdl2::init().map_err(AppError::Sdl)?;
+        let video = sdl.video().map_err(AppError::Sdl)?;
+        let gl_attr = video.gl_attr();
+        gl_attr.set_context_profile(self.gl_profile);
+        gl_attr.set_context_version(self.gl_version.0, self.gl_version.1);
+        let mut flags = gl_attr.set_context_flags();
+        if self.gl_profile == GLProfile::Core {
+            flags.forward_compatible();
+        }
+        if self.debug_context {
+            flags.debug();
+        }
+        flags.set();
+        gl_attr.set_depth_size(self.depth_bits);
+        gl_attr.set_stencil_size(self.stencil_bits);
+        if self.msaa_samples > 0 {
+            gl_attr.set_multisample_buffers(1);
+            gl_attr.set_multisample_samples(self.msaa_samples);
+        } else {
+            gl_attr.set_multisample_buffers(0);
+            gl_attr.set_multisample_samples(0);
+        }
+
+        let mut builder = video.window(&self.title, self.width, self.height);
+        builder.opengl();
+        match self.position {
+            Some((x, y)) => builder.position(x, y),
+            None => builder.position_centered(),
+        };
+        match self.mode {
+            WindowMode::Windowed => &mut builder,
+            WindowMode::Fullscreen => builder.fullscreen(),
+            WindowMode::Borderless => builder.fullscreen_desktop(),
+        };
+        if self.resizable {
+            builder.resizable();
+        }
+        if self.high_dpi {
+            builder.allow_highdpi();
+        }
+        if self.hidden {
+            builder.hidden();
+        }
+        let mut window = builder.build().map_err(AppError::Window)?;
+        if let Some((width, height, mut rgba)) = self.icon {
+            let surface =
+                Surface::from_data(&mut rgba, width, height, width * 4, PixelFormatEnum::RGBA32)
+                    .map_err(AppError::Icon)?;
+            window.set_icon(surface);
+        }
+
+        let gl_context = window.gl_create_context().map_err(AppError::Context)?;
+        let interval = if self.vsync {
+            SwapInterval::VSync
+        } else {
+            SwapInterval::Immediate
+        };
+        // Not every driver lets us pick; keep whatever it gives us.
+        if let Err(e) = video.gl_set_swap_interval(interval) {
+            ::log::warn!("can't set swap interval: {e}");
+        }
+
+        let (window_width, window_height) = window.drawable_size();
+        Ok(App {
             video,
+            event_loop: sdl.event_pump().map_err(AppError::Sdl)?,
             window,
-            event_loop: sdl.event_pump().unwrap(),
             gl_context,
             window_width,
             window_height,
-        }
+        })
+    }
+}
+
+impl App {
+    pub fn builder(title: &str, window_width: u32, window_height: u32) -> AppBuilder {
+        AppBuilder::new(title, window_width, window_height)
+    }
+    pub fn new(title: &str, window_width: u32, window_height: u32) -> Result<Self, AppError> {
+        AppBuilder::new(title, window_width, window_height).build()
     }
     pub fn window(&self) -> &Window {
         &self.window
@@ -63,4 +235,95 @@ impl App {
     pub fn video(&self) -> &VideoSubsystem {
         &self.video
     }
+
+    pub fn run<G: Game>(&mut self, graphics: &Graphics, scene: &mut G, config: RunConfig) {
+        if let Some(vsync) = config.vsync {
+            let interval = if vsync {
+                SwapInterval::VSync
+            } else {
+                SwapInterval::Immediate
+            };
+            if let Err(e) = self.video.gl_set_swap_interval(interval) {
+                ::log::warn!("can't set swap interval: {e}");
+            }
+        }
+
+        assert!(
+            config.fixed_timestep.is_finite() && config.fixed_timestep > 0.0,
+            "RunConfig::fixed_timestep must be positive, got {}",
+            config.fixed_timestep
+        );
+        scene.init(graphics);
+        let mut control = Control::default();
+        let mut time = Time::new(config.fixed_timestep);
+        let fixed_delta = config.fixed_timestep;
+        let max_accumulated = fixed_delta * config.max_fixed_steps as f32;
+        let mut accumulator = 0.0;
+        let mut last_time = Instant::now();
+
+        while !control.is_quitting() {
+            let _span = trace_span!("frame");
+            let frame_start = Instant::now();
+            let events = stats::time(Phase::Events, || {
+                self.event_loop.poll_iter().collect::<Vec<_>>()
+            });
+            for event in events {
+                graphics.handle_event(&event);
+                match event {
+                    Event::Quit { .. } => control.quit(),
+                    Event::KeyDown {
+                        keycode: Some(key),
+                        repeat: false,
+                        ..
+                    } => {
+                        if Some(key) == config.pause_key {
+                            control.toggle_pause();
+                        } else if Some(key) == config.step_key {
+                            control.step();
+                        }
+                    }
+                    _ => {}
+                }
+                scene.event(&event, &mut control);
+            }
+            if control.is_quitting() {
+                break;
+            }
+
+            // The UI pass opens here so behaviors can build panels while updating.
+            graphics.begin_frame();
+            let delta_time = frame_start.duration_since(last_time).as_secs_f32();
+            last_time = frame_start;
+            let update_start = Instant::now();
+            if !control.is_paused() {
+                time.advance(delta_time);
+                accumulator = (accumulator + time.delta()).min(max_accumulated);
+                while accumulator >= fixed_delta {
+                    scene.fixed_update(&time);
+                    accumulator -= fixed_delta;
+                }
+                scene.update(&mut time);
+            } else if control.take_step() {
+                time.advance(fixed_delta);
+                scene.fixed_update(&time);
+                scene.update(&mut time);
+            }
+            stats::record(Phase::Update, update_start.elapsed());
+
+            stats::time(Phase::Render, || {
+                graphics.begin_draw();
+                scene.draw(graphics);
+            });
+            graphics.end_frame(&self.window);
+
+            if let Some(fps) = config.max_frame_rate.filter(|fps| *fps > 0) {
+                let target = Duration::from_secs_f64(1.0 / fps as f64);
+                let elapsed = frame_start.elapsed();
+                if elapsed < target {
+                    std::thread::sleep(target - elapsed);
+                }
+            }
+        }
+        scene.shutdown();
+    }
 }
@@ -0,0 +1,544 @@
+//! Shared assets loaded by path. `Assets::load` reads a path once and hands
+//! out `Handle`s to the result; loading the same path again, or looking up
+//! its `AssetId`, returns the same asset while any handle to it is alive.
+//! Dropping the last handle frees the asset along with its GL objects.
+//!
+//! `Assets::load_async` decodes on worker threads instead and returns a
+//! pending handle right away. `Assets::update`, called once a frame, does
+//! the GL uploads of finished loads within a time budget. Until then
+//! `Handle::get` returns the placeholder set for its type, if any. Only the
+//! `Loaded` handles from `load` and `insert` deref to the asset.
+use crate::prelude::*;
+use std::any::{Any, TypeId};
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::{Rc, Weak};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// Stable id of an asset path, the same in every run.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct AssetId(pub u64);
+impl AssetId {
+    /// Normalized the way `Vfs` reads paths, so spellings of a path share an
+    /// id. Paths `Vfs` would reject are hashed as they are.
+    pub fn from_path(path: &str) -> Self {
+        let path = crate::vfs::normalize(path).unwrap_or_else(|_| path.to_string());
+        Self(fnv1a(path.as_bytes()))
+    }
+}
+
+/// 64-bit FNV-1a.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LoadState {
+    Pending,
+    Ready,
+    Failed(String),
+}
+
+struct Slot<T> {
+    id: AssetId,
+    path: String,
+    state: RefCell<LoadState>,
+    asset: OnceCell<T>,
+    /// What `Handle::get` returns until the asset is ready.
+    placeholder: Option<Rc<T>>,
+}
+impl<T> Slot<T> {
+    fn finish(&self, result: Result<T, String>) {
+        match result {
+            Ok(asset) => {
+                // A blocking `load` may have got there first.
+                if self.asset.set(asset).is_ok() {
+                    *self.state.borrow_mut() = LoadState::Ready;
+                }
+            }
+            Err(error) => {
+                ::log::error!("failed to load {}: {error}", self.path);
+                if self.asset.get().is_none() {
+                    *self.state.borrow_mut() = LoadState::Failed(error);
+                }
+            }
+        }
+    }
+}
+
+/// A counted reference to an asset that may still be loading. Cheap to clone.
+pub struct Handle<T>(Rc<Slot<T>>);
+impl<T> Handle<T> {
+    pub fn id(&self) -> AssetId {
+        self.0.id
+    }
+    /// The path it was loaded from, or the name it was inserted under.
+    pub fn path(&self) -> &str {
+        &self.0.path
+    }
+    pub fn state(&self) -> LoadState {
+        self.0.state.borrow().clone()
+    }
+    pub fn is_ready(&self) -> bool {
+        self.0.asset.get().is_some()
+    }
+    /// The asset, or the placeholder if it isn't ready.
+    pub fn get(&self) -> Option<&T> {
+        self.0.asset.get().or(self.0.placeholder.as_deref())
+    }
+    /// A handle that derefs to the asset, once it's ready.
+    /// A handle that never loads, for tests that only need one to exist.
+    #[cfg(test)]
+    pub(crate) fn pending(path: &str) -> Self {
+        Handle(Rc::new(Slot {
+            id: AssetId::from_path(path),
+            path: path.to_string(),
+            state: RefCell::new(LoadState::Pending),
+            asset: OnceCell::new(),
+            placeholder: None,
+        }))
+    }
+    pub fn loaded(&self) -> Option<Loaded<T>> {
+        self.is_ready().then(|| Loaded(self.clone()))
+    }
+}
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self(Rc::clone(&self.0))
+    }
+}
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({:?}, {:?})", self.0.path, self.0.state.borrow())
+    }
+}
+
+/// A `Handle` to an asset that is known to be ready, so it derefs to it.
+pub struct Loaded<T>(Handle<T>);
+impl<T> Loaded<T> {
+    pub fn handle(&self) -> Handle<T> {
+        self.0.clone()
+    }
+}
+impl<T> Clone for Loaded<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+impl<T> Deref for Loaded<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Only made once the asset is set, which is never undone.
+        self.0 .0.asset.get().unwrap()
+    }
+}
+impl<T> From<Loaded<T>> for Handle<T> {
+    fn from(loaded: Loaded<T>) -> Self {
+        loaded.0
+    }
+}
+
+/// Something `Assets` can load from a path, in two steps so the slow part
+/// can happen off the main thread.
+pub trait Asset: Sized + 'static {
+    /// What `decode` produces, such as `MeshData` for a mesh.
+    type Decoded: Send + 'static;
+    /// Reads and parses the files for `path`. Runs on a loader thread for
+    /// `load_async`, so it can't touch GL.
+    fn decode(vfs: &Vfs, path: &str) -> Result<Self::Decoded, String>;
+    /// Creates the asset on the main thread, loading any assets it depends on
+    /// through `context`.
+    fn upload(context: &LoadContext, decoded: Self::Decoded) -> Result<Self, String>;
+}
+
+pub struct LoadContext<'a> {
+    pub assets: &'a Assets,
+    pub graphics: &'a Graphics,
+    asynchronous: bool,
+}
+impl LoadContext<'_> {
+    /// Loads a dependency, in the background if the asset itself is.
+    pub fn load<T: Asset>(&self, path: &str) -> Result<Handle<T>, String> {
+        if self.asynchronous {
+            Ok(self.assets.load_async(path))
+        } else {
+            self.assets.load(self.graphics, path).map(Handle::from)
+        }
+    }
+}
+
+type Decoded = Result<Box<dyn Any + Send>, String>;
+type Job = Box<dyn FnOnce() -> Decoded + Send>;
+type Upload = Box<dyn FnOnce(&LoadContext, Decoded)>;
+
+/// Worker threads decoding files, and the uploads waiting for their results.
+struct Loader {
+    jobs: mpsc::Sender<(u64, Job)>,
+    results: mpsc::Receiver<(u64, Decoded)>,
+    uploads: HashMap<u64, Upload>,
+    next_job: u64,
+}
+impl Loader {
+    fn new() -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<(u64, Job)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let threads = std::thread::available_parallelism()
+            .map_or(2, |n| n.get().saturating_sub(1))
+            .clamp(1, 4);
+        for i in 0..threads {
+            let jobs = Arc::clone(&job_receiver);
+            let results = result_sender.clone();
+            std::thread::Builder::new()
+                .name(format!("asset loader {i}"))
+                .spawn(move || loop {
+                    let job = jobs.lock().unwrap().recv();
+                    let Ok((id, decode)) = job else {
+                        break;
+                    };
+                    // A decoder bug fails its asset rather than the thread.
+                    let decoded = panic::catch_unwind(AssertUnwindSafe(decode))
+                        .unwrap_or_else(|panic| Err(panic_message(&*panic)));
+                    if results.send((id, decoded)).is_err() {
+                        break;
+                    }
+                })
+                .expect("Cannot spawn asset loader thread");
+        }
+        Self {
+            jobs,
+            results,
+            uploads: HashMap::new(),
+            next_job: 0,
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    format!("decoder panicked: {message}")
+}
+
+type Cache<T> = HashMap<AssetId, Weak<Slot<T>>>;
+
+pub struct Assets {
+    /// Shared with the loader threads.
+    vfs: Arc<Vfs>,
+    /// A `Cache<T>` per asset type.
+    caches: RefCell<HashMap<TypeId, Box<dyn Any>>>,
+    /// An `Rc<T>` per asset type.
+    placeholders: RefCell<HashMap<TypeId, Box<dyn Any>>>,
+    /// Started by the first `load_async`.
+    loader: RefCell<Option<Loader>>,
+    /// GL upload time `update` may spend per frame.
+    pub upload_budget: Duration,
+}
+impl Assets {
+    /// Loads from `vfs`, which can still be mounted on afterwards.
+    pub fn new(vfs: Vfs) -> Self {
+        Self {
+            vfs: Arc::new(vfs),
+            caches: RefCell::new(HashMap::new()),
+            placeholders: RefCell::new(HashMap::new()),
+            loader: RefCell::new(None),
+            upload_budget: Duration::from_millis(4),
+        }
+    }
+    pub fn vfs(&self) -> &Vfs {
+        &self.vfs
+    }
+    /// What `Handle::get` returns for `T` assets while loading, e.g. a checker
+    /// texture. Only affects loads started afterwards.
+    pub fn set_placeholder<T: 'static>(&self, asset: T) {
+        self.placeholders
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Box::new(Rc::new(asset)));
+    }
+    /// The asset at `path`, loading it on this thread unless it's already
+    /// loaded. Finishes the load if it's still pending in the background.
+    pub fn load<T: Asset>(&self, graphics: &Graphics, path: &str) -> Result<Loaded<T>, String> {
+        let id = AssetId::from_path(path);
+        let handle = self.get(id).unwrap_or_else(|| self.new_handle(id, path));
+        if handle.0.asset.get().is_none() {
+            let context = LoadContext {
+                assets: self,
+                graphics,
+                asynchronous: false,
+            };
+            let result = T::decode(&self.vfs, path).and_then(|data| T::upload(&context, data));
+            handle.0.finish(result);
+        }
+        match handle.state() {
+            LoadState::Failed(error) => Err(error),
+            _ => Ok(Loaded(handle)),
+        }
+    }
+    /// The asset at `path`, decoded on a loader thread unless it's already
+    /// loaded or loading. Check `Handle::state` to see when it's ready.
+    pub fn load_async<T: Asset>(&self, path: &str) -> Handle<T> {
+        let id = AssetId::from_path(path);
+        if let Some(handle) = self.get(id) {
+            return handle;
+        }
+        let handle = self.new_handle(id, path);
+        let vfs = Arc::clone(&self.vfs);
+        let owned_path = path.to_string();
+        let job: Job = Box::new(move || {
+            T::decode(&vfs, &owned_path).map(|data| Box::new(data) as Box<dyn Any + Send>)
+        });
+        let slot = Rc::downgrade(&handle.0);
+        let upload: Upload = Box::new(move |context, decoded| {
+            // Nobody wants it any more.
+            let Some(slot) = slot.upgrade() else {
+                return;
+            };
+            let result = decoded.and_then(|data| {
+                let data = *data.downcast::<T::Decoded>().unwrap();
+                T::upload(context, data)
+            });
+            slot.finish(result);
+        });
+        let mut loader = self.loader.borrow_mut();
+        let loader = loader.get_or_insert_with(Loader::new);
+        let job_id = loader.next_job;
+        loader.next_job += 1;
+        loader.uploads.insert(job_id, upload);
+        if loader.jobs.send((job_id, job)).is_err() {
+            loader.uploads.remove(&job_id);
+            handle
+                .0
+                .finish(Err("asset loader threads stopped".to_string()));
+        }
+        handle
+    }
+    /// Uploads background loads that finished decoding, until
+    /// `upload_budget` runs out. Call once a frame.
+    pub fn update(&self, graphics: &Graphics) {
+        let start = Instant::now();
+        let context = LoadContext {
+            assets: self,
+            graphics,
+            asynchronous: true,
+        };
+        while start.elapsed() < self.upload_budget {
+            // Uploads may start more loads, so don't hold the loader.
+            let next = self.loader.borrow_mut().as_mut().and_then(|loader| {
+                let (id, decoded) = loader.results.try_recv().ok()?;
+                Some((loader.uploads.remove(&id)?, decoded))
+            });
+            let Some((upload, decoded)) = next else {
+                break;
+            };
+            upload(&context, decoded);
+        }
+    }
+    /// How many background loads haven't been uploaded yet.
+    pub fn loading(&self) -> usize {
+        self.loader
+            .borrow()
+            .as_ref()
+            .map_or(0, |loader| loader.uploads.len())
+    }
+    /// A loaded or loading asset by id, if anything still holds a handle to it.
+    pub fn get<T: 'static>(&self, id: AssetId) -> Option<Handle<T>> {
+        let caches = self.caches.borrow();
+        let cache = caches.get(&TypeId::of::<T>())?.downcast_ref::<Cache<T>>()?;
+        cache.get(&id)?.upgrade().map(Handle)
+    }
+    /// Makes an asset built at runtime, such as a procedural mesh, available
+    /// under `name` as if it had been loaded from that path.
+    pub fn insert<T: 'static>(&self, name: &str, asset: T) -> Loaded<T> {
+        let handle = self.new_handle(AssetId::from_path(name), name);
+        handle.0.finish(Ok(asset));
+        Loaded(handle)
+    }
+    /// A new pending handle, cached under `id`.
+    fn new_handle<T: 'static>(&self, id: AssetId, path: &str) -> Handle<T> {
+        let placeholder = self
+            .placeholders
+            .borrow()
+            .get(&TypeId::of::<T>())
+            .and_then(|placeholder| placeholder.downcast_ref::<Rc<T>>())
+            .cloned();
+        let slot = Rc::new(Slot {
+            id,
+            path: path.to_string(),
+            state: RefCell::new(LoadState::Pending),
+            asset: OnceCell::new(),
+            placeholder,
+        });
+        let mut caches = self.caches.borrow_mut();
+        let cache = caches
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Cache::<T>::new()))
+            .downcast_mut::<Cache<T>>()
+            .unwrap();
+        cache.retain(|_, slot| slot.strong_count() > 0);
+        cache.insert(id, Rc::downgrade(&slot));
+        Handle(slot)
+    }
+    /// How many assets of type `T` are alive.
+    pub fn loaded<T: 'static>(&self) -> usize {
+        let caches = self.caches.borrow();
+        caches
+            .get(&TypeId::of::<T>())
+            .and_then(|cache| cache.downcast_ref::<Cache<T>>())
+            .map_or(0, |cache| {
+                cache
+                    .values()
+                    .filter(|slot| slot.strong_count() > 0)
+                    .count()
+            })
+    }
+}
+
+/// `path` names a pair of files, `path.vs` and `path.fs`.
+impl Asset for Shader {
+    type Decoded = (String, String);
+    fn decode(vfs: &Vfs, path: &str) -> Result<Self::Decoded, String> {
+        Ok((
+            vfs.read_to_string(&format!("{path}.vs"))?,
+            vfs.read_to_string(&format!("{path}.fs"))?,
+        ))
+    }
+    fn upload(context: &LoadContext, (vertex, fragment): Self::Decoded) -> Result<Self, String> {
+        Shader::new(context.graphics, &vertex, &fragment)
+    }
+}
+
+/// Cooked mesh files (see `MeshFile`), memory-mapped when they're loose
+/// files, or Wavefront OBJ files converted to one on the loader thread. All
+/// OBJ objects are merged into one mesh, one submesh per object; normals are
+/// generated if the file has none.
+impl Asset for GpuMesh {
+    type Decoded = MappedMeshFile;
+    fn decode(vfs: &Vfs, path: &str) -> Result<Self::Decoded, String> {
+        let file = match vfs.real_path(path) {
+            Some(real_path) => MappedMeshFile::open(real_path)?,
+            None => MappedMeshFile::from_bytes(vfs.read_file(path)?),
+        };
+        if MeshFile::is_mesh_file(file.bytes()) {
+            file.parse()?;
+            return Ok(file);
+        }
+        let data = match path.rsplit_once('.').map(|(_, extension)| extension) {
+            Some("obj") => load_obj(file.bytes())?,
+            _ => return Err("unsupported mesh format".to_string()),
+        };
+        Ok(MappedMeshFile::from_bytes(MeshFile::write(&data)?))
+    }
+    fn upload(context: &LoadContext, file: Self::Decoded) -> Result<Self, String> {
+        // `decode` already checked the indices.
+        let file = MeshFile::parse_unchecked(file.bytes())?;
+        Ok(GpuMesh::from_file(context.graphics, &file))
+    }
+}
+
+fn load_obj(bytes: &[u8]) -> Result<MeshData, String> {
+    let (models, _) = tobj::load_obj_buf(
+        &mut std::io::BufReader::new(bytes),
+        &tobj::GPU_LOAD_OPTIONS,
+        |_| Err(tobj::LoadError::GenericFailure),
+    )
+    .map_err(|e| format!("invalid OBJ: {e}"))?;
+    let mut data = MeshData::default();
+    let mut missing_normals = false;
+    for model in models {
+        let mesh = model.mesh;
+        let count = mesh.positions.len() / 3;
+        missing_normals |= mesh.normals.len() != count * 3;
+        let vertices = (0..count)
+            .map(|i| Vertex {
+                position: Vec3::from_slice(&mesh.positions[i * 3..]),
+                normal: mesh
+                    .normals
+                    .get(i * 3..i * 3 + 3)
+                    .map_or(Vec3::ZERO, Vec3::from_slice),
+                uv: mesh
+                    .texcoords
+                    .get(i * 2..i * 2 + 2)
+                    .map_or(Vec2::ZERO, Vec2::from_slice),
+                tangent: Vec4::ZERO,
+            })
+            .collect();
+        // The passes below index without checking.
+        let model = MeshData::new(vertices, mesh.indices);
+        model.validate()?;
+        data.append(&model);
+    }
+    if missing_normals {
+        processing::smooth_normals(&mut data, 60f32.to_radians());
+    } else {
+        processing::generate_tangents(&mut data);
+    }
+    Ok(data)
+}
+
+/// PNG images.
+impl Asset for Texture {
+    type Decoded = TextureData;
+    fn decode(vfs: &Vfs, path: &str) -> Result<Self::Decoded, String> {
+        TextureData::decode(&vfs.read_file(path)?)
+    }
+    fn upload(context: &LoadContext, data: Self::Decoded) -> Result<Self, String> {
+        Texture::new(context.graphics, &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(loader: &Loader, id: u64, job: Job) -> Decoded {
+        loader.jobs.send((id, job)).unwrap();
+        let (done, decoded) = loader.results.recv().unwrap();
+        assert_eq!(done, id);
+        decoded
+    }
+
+    #[test]
+    fn loader_threads_survive_panicking_decoders() {
+        let loader = Loader::new();
+        let error = run(&loader, 0, Box::new(|| panic!("bad file"))).err();
+        assert_eq!(error.as_deref(), Some("decoder panicked: bad file"));
+        // More panics than there are threads, and jobs still run.
+        for id in 1..=8 {
+            let job: Job = Box::new(move || panic!("bad file {id}"));
+            assert!(run(&loader, id, job).is_err());
+        }
+        let decoded = run(&loader, 9, Box::new(|| Ok(Box::new(9u32))));
+        assert_eq!(decoded.unwrap().downcast_ref::<u32>(), Some(&9));
+    }
+
+    #[test]
+    fn obj_without_normals_gets_smooth_ones() {
+        let data = load_obj(b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        data.validate().unwrap();
+        assert_eq!(data.indices, [0, 1, 2]);
+        assert!(data.vertices.iter().all(|v| v.normal == Vec3::Z));
+    }
+
+    #[test]
+    fn bad_obj_is_an_error() {
+        for obj in [
+            "v 0 0 0\nf 1 2 3\n",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 -9\n",
+        ] {
+            assert!(load_obj(obj.as_bytes()).is_err(), "{obj}");
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Packs an assets directory into a `.pak` archive for shipping, checking on
+//! the way that every asset aurion knows how to load does load.
+//!
+//!     cargo run -p aurion --bin aurion-pack -- assets assets.pak --compress
+//!
+//! OBJ meshes are stored converted to cooked mesh files under the same path,
+//! which `Assets` loads without parsing. Shaders are compiled on a hidden GL
+//! context when one can be created, and only checked for a `#version` line
+//! and `main` otherwise; `--no-gl` skips the context.
+use aurion::log;
+use aurion::prelude::*;
+use std::path::Path;
+
+const USAGE: &str = "usage: aurion-pack <assets dir> <output.pak> [--compress] [--no-gl]";
+
+fn main() {
+    aurion::logging::init();
+    let mut paths = vec![];
+    let (mut compress, mut use_gl) = (false, true);
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--compress" => compress = true,
+            "--no-gl" => use_gl = false,
+            _ if arg.starts_with('-') => exit_with_usage(),
+            _ => paths.push(arg),
+        }
+    }
+    let [input, output] = &paths[..] else {
+        exit_with_usage();
+    };
+    if let Err(e) = pack(Path::new(input), Path::new(output), compress, use_gl) {
+        log::error!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+fn pack(input: &Path, output: &Path, compress: bool, use_gl: bool) -> Result<(), String> {
+    let mut files = vec![];
+    collect(input, "", &mut files)?;
+    files.sort();
+    let vfs = Vfs::new();
+    vfs.mount("", DirMount::new(input), 0);
+
+    let context = if use_gl { hidden_context() } else { None };
+    let graphics = context.as_ref().map(|(_, graphics)| graphics);
+    let failed = files
+        .iter()
+        .filter(|path| match check(&vfs, path, graphics) {
+            Ok(()) => false,
+            Err(e) => {
+                log::error!("{path}: {e}");
+                true
+            }
+        })
+        .count();
+    if failed > 0 {
+        return Err(format!("{failed} of {} files failed to check", files.len()));
+    }
+
+    let mut pak = PakWriter::create(output)?;
+    for path in &files {
+        let contents = if path.ends_with(".obj") {
+            <GpuMesh as Asset>::decode(&vfs, path)?.bytes().to_vec()
+        } else {
+            vfs.read_file(path)?
+        };
+        pak.add(path, &contents, compress)?;
+    }
+    let entries = pak.finish()?;
+    let size: u64 = entries.iter().map(|entry| entry.size).sum();
+    let written = std::fs::metadata(output).map_or(0, |metadata| metadata.len());
+    log::info!(
+        "packed {} files, {size} bytes into {written} bytes in {}",
+        entries.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Every file under `dir`, as paths relative to the assets root. Other paks
+/// are left out, in case the output goes inside the assets directory.
+fn collect(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let name = entry.file_name();
+        let name = name
+            .to_str()
+            .ok_or_else(|| format!("{}: not valid UTF-8", entry.path().display()))?;
+        let path = format!("{prefix}{name}");
+        if entry.path().is_dir() {
+            collect(&entry.path(), &format!("{path}/"), files)?;
+        } else if !name.ends_with(".pak") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn hidden_context() -> Option<(App, Graphics)> {
+    match App::builder("aurion-pack", 64, 64)
+        .hidden(true)
+        .debug_context(false)
+        .build()
+    {
+        Ok(app) => {
+            let graphics = Graphics::new(&app);
+            Some((app, graphics))
+        }
+        Err(e) => {
+            log::warn!("{e}, shaders will only be checked roughly");
+            None
+        }
+    }
+}
+
+/// Decodes `path` the way `Assets` would, by extension.
+fn check(vfs: &Vfs, path: &str, graphics: Option<&Graphics>) -> Result<(), String> {
+    let Some((stem, extension)) = path.rsplit_once('.') else {
+        return Ok(());
+    };
+    match extension {
+        "vs" => {
+            let (vertex, fragment) = <Shader as Asset>::decode(vfs, stem)?;
+            match graphics {
+                Some(graphics) => Shader::new(graphics, &vertex, &fragment).map(drop),
+                None => check_glsl(&vertex).and_then(|_| check_glsl(&fragment)),
+            }
+        }
+        "fs" if !vfs.exists(&format!("{stem}.vs")) => Err("no matching .vs file".to_string()),
+        "obj" | "amesh" => <GpuMesh as Asset>::decode(vfs, path).map(drop),
+        "png" => <Texture as Asset>::decode(vfs, path).map(drop),
+        "mat" => <Material as Asset>::decode(vfs, path).map(drop),
+        _ => Ok(()),
+    }
+}
+
+fn check_glsl(source: &str) -> Result<(), String> {
+    let first = source.lines().map(str::trim).find(|line| !line.is_empty());
+    if !first.is_some_and(|line| line.starts_with("#version")) {
+        return Err("shader doesn't start with #version".to_string());
+    }
+    if !source.contains("void main") {
+        return Err("shader has no main function".to_string());
+    }
+    Ok(())
+}
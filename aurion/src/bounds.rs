@@ -0,0 +1,138 @@
+//! Bounding volumes and the view frustum test used to skip off-screen draws.
+use crate::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+impl Aabb {
+    /// An inverted box that any point grows; `is_empty` until then.
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3::splat(f32::INFINITY),
+        max: Vec3::splat(f32::NEG_INFINITY),
+    };
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, p| aabb.grow(p))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+    pub fn grow(self, point: Vec3) -> Self {
+        Self::new(self.min.min(point), self.max.max(point))
+    }
+    pub fn union(self, other: Aabb) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+    /// The axis-aligned box enclosing this one after `transform`.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = transform.transform_point3(self.center());
+        let extents = self.half_extents();
+        let half = transform.x_axis.truncate().abs() * extents.x
+            + transform.y_axis.truncate().abs() * extents.y
+            + transform.z_axis.truncate().abs() * extents.z;
+        Self::new(center - half, center + half)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+impl BoundingSphere {
+    /// Centered on the points' bounding box, which is loose but cheap.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3> + Clone) -> Self {
+        let aabb = Aabb::from_points(points.clone());
+        if aabb.is_empty() {
+            return Self {
+                center: Vec3::ZERO,
+                radius: 0.0,
+            };
+        }
+        let center = aabb.center();
+        let radius = points
+            .into_iter()
+            .map(|p| p.distance_squared(center))
+            .fold(0.0, f32::max)
+            .sqrt();
+        Self { center, radius }
+    }
+    /// The sphere through the corners of `aabb`.
+    pub fn from_aabb(aabb: &Aabb) -> Self {
+        if aabb.is_empty() {
+            return Self {
+                center: Vec3::ZERO,
+                radius: 0.0,
+            };
+        }
+        Self {
+            center: aabb.center(),
+            radius: aabb.half_extents().length(),
+        }
+    }
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let scale = transform
+            .x_axis
+            .truncate()
+            .length_squared()
+            .max(transform.y_axis.truncate().length_squared())
+            .max(transform.z_axis.truncate().length_squared())
+            .sqrt();
+        Self {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Six inward-facing planes as `(normal, distance)`, so a point `p` is inside
+/// a plane when `normal.dot(p) + distance >= 0`.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+impl Frustum {
+    pub fn from_view_projection(view_projection: &Mat4) -> Self {
+        let rows = [
+            view_projection.row(0),
+            view_projection.row(1),
+            view_projection.row(2),
+            view_projection.row(3),
+        ];
+        let planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[3] + rows[2],
+            rows[3] - rows[2],
+        ]
+        .map(|plane| plane / plane.truncate().length());
+        Self { planes }
+    }
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let (center, half) = (aabb.center(), aabb.half_extents());
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            normal.dot(center) + plane.w >= -normal.abs().dot(half)
+        })
+    }
+}
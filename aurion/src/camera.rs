@@ -0,0 +1,38 @@
+use crate::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Vertical field of view in radians.
+    pub fov_y: f32,
+    pub aspect_ratio: f32,
+    pub near: f32,
+    pub far: f32,
+}
+impl Camera {
+    pub fn new(aspect_ratio: f32) -> Self {
+        Self {
+            position: Vec3::new(0.0, 0.0, 5.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            fov_y: 45.0_f32.to_radians(),
+            aspect_ratio,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_projection(&self.view_projection())
+    }
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position, self.target, self.up)
+    }
+    pub fn projection(&self) -> Mat4 {
+        Mat4::perspective_rh_gl(self.fov_y, self.aspect_ratio, self.near, self.far)
+    }
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+}
@@ -0,0 +1,115 @@
+//! Immediate-mode debug lines. Anything queued during a frame is drawn on top
+//! of the scene by `Graphics::end_frame` and then discarded.
+use crate::camera::Camera;
+use crate::prelude::*;
+use std::cell::RefCell;
+use std::f32::consts::TAU;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DebugVertex {
+    pub position: Vec3,
+    pub color: Vec4,
+}
+
+thread_local! {
+    static LINES: RefCell<Vec<DebugVertex>> = const { RefCell::new(Vec::new()) };
+}
+
+pub const RED: Vec4 = Vec4::new(1.0, 0.0, 0.0, 1.0);
+pub const GREEN: Vec4 = Vec4::new(0.0, 1.0, 0.0, 1.0);
+pub const BLUE: Vec4 = Vec4::new(0.0, 0.0, 1.0, 1.0);
+pub const YELLOW: Vec4 = Vec4::new(1.0, 1.0, 0.0, 1.0);
+pub const WHITE: Vec4 = Vec4::ONE;
+
+const CIRCLE_SEGMENTS: usize = 24;
+
+pub fn line(from: Vec3, to: Vec3, color: Vec4) {
+    LINES.with_borrow_mut(|lines| {
+        lines.push(DebugVertex {
+            position: from,
+            color,
+        });
+        lines.push(DebugVertex {
+            position: to,
+            color,
+        });
+    });
+}
+
+/// Draws the twelve edges between eight corners ordered as in `box_corners`.
+fn edges(corners: &[Vec3; 8], color: Vec4) {
+    const EDGES: [(usize, usize); 12] = [
+        (0, 1),
+        (1, 3),
+        (3, 2),
+        (2, 0),
+        (4, 5),
+        (5, 7),
+        (7, 6),
+        (6, 4),
+        (0, 4),
+        (1, 5),
+        (2, 6),
+        (3, 7),
+    ];
+    for (a, b) in EDGES {
+        line(corners[a], corners[b], color);
+    }
+}
+
+fn box_corners(min: Vec3, max: Vec3) -> [Vec3; 8] {
+    std::array::from_fn(|i| {
+        Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        )
+    })
+}
+
+/// Axis-aligned box in world space.
+pub fn aabb(min: Vec3, max: Vec3, color: Vec4) {
+    edges(&box_corners(min, max), color);
+}
+
+/// Box of the given half extents, placed and oriented by `transform`.
+pub fn oriented_box(transform: &Mat4, half_extents: Vec3, color: Vec4) {
+    let corners = box_corners(-half_extents, half_extents).map(|c| transform.transform_point3(c));
+    edges(&corners, color);
+}
+
+pub fn circle(center: Vec3, normal: Vec3, radius: f32, color: Vec4) {
+    let (u, v) = normal.normalize_or(Vec3::Y).any_orthonormal_pair();
+    let point = |i: usize| {
+        let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+        center + (u * angle.cos() + v * angle.sin()) * radius
+    };
+    for i in 0..CIRCLE_SEGMENTS {
+        line(point(i), point(i + 1), color);
+    }
+}
+
+pub fn sphere(center: Vec3, radius: f32, color: Vec4) {
+    circle(center, Vec3::X, radius, color);
+    circle(center, Vec3::Y, radius, color);
+    circle(center, Vec3::Z, radius, color);
+}
+
+/// The local X/Y/Z axes of `transform` in red, green and blue.
+pub fn axes(transform: &Mat4, size: f32) {
+    let origin = transform.transform_point3(Vec3::ZERO);
+    line(origin, transform.transform_point3(Vec3::X * size), RED);
+    line(origin, transform.transform_point3(Vec3::Y * size), GREEN);
+    line(origin, transform.transform_point3(Vec3::Z * size), BLUE);
+}
+
+pub fn frustum(camera: &Camera, color: Vec4) {
+    let inverse = camera.view_projection().inverse();
+    let corners = box_corners(Vec3::NEG_ONE, Vec3::ONE).map(|c| inverse.project_point3(c));
+    edges(&corners, color);
+}
+
+pub(crate) fn take_lines() -> Vec<DebugVertex> {
+    LINES.with_borrow_mut(std::mem::take)
+}
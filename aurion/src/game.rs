@@ -0,0 +1,69 @@
+use crate::prelude::*;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+
+#[derive(Default)]
+pub struct Control {
+    quit: bool,
+    paused: bool,
+    step: bool,
+}
+impl Control {
+    pub fn quit(&mut self) {
+        self.quit = true;
+    }
+    pub fn is_quitting(&self) -> bool {
+        self.quit
+    }
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    /// Advances a paused game by exactly one fixed step on the next frame.
+    pub fn step(&mut self) {
+        self.step = true;
+    }
+    pub(crate) fn take_step(&mut self) -> bool {
+        std::mem::take(&mut self.step)
+    }
+}
+
+/// Callbacks driven by `App::run`. Only `draw` is required.
+pub trait Game {
+    fn init(&mut self, _graphics: &Graphics) {}
+    fn event(&mut self, _event: &Event, _control: &mut Control) {}
+    fn fixed_update(&mut self, _time: &Time) {}
+    fn update(&mut self, _time: &mut Time) {}
+    fn draw(&mut self, graphics: &Graphics);
+    fn shutdown(&mut self) {}
+}
+
+pub struct RunConfig {
+    /// Seconds simulated by each `Game::fixed_update` call. Must be positive;
+    /// `App::run` panics otherwise.
+    pub fixed_timestep: f32,
+    /// Upper bound on fixed steps per frame, so a long hitch doesn't spiral.
+    pub max_fixed_steps: u32,
+    pub max_frame_rate: Option<u32>,
+    /// Overrides the swap interval chosen in `AppBuilder`.
+    pub vsync: Option<bool>,
+    pub pause_key: Option<Keycode>,
+    pub step_key: Option<Keycode>,
+}
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            fixed_timestep: 1.0 / 60.0,
+            max_fixed_steps: 8,
+            max_frame_rate: None,
+            vsync: None,
+            pause_key: Some(Keycode::Pause),
+            step_key: Some(Keycode::F10),
+        }
+    }
+}
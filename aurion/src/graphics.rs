@@ -1,81 +1,432 @@
 use crate::app;
+use crate::camera::Camera;
+use crate::debug::{self, DebugVertex};
 use crate::prelude::*;
+use crate::render_queue::{DrawItem, RenderQueue};
+use crate::stats::{self, FrameStats, GpuTimer, Phase};
+use crate::text::{Font, TextRenderer};
+use crate::trace::trace_span;
+use crate::ui::UiOverlay;
+use sdl2::event::Event;
+use std::cell::{Cell, RefCell};
 use std::mem::offset_of;
+use std::sync::Arc;
+
+const DEBUG_VS: &str = r#"#version 330
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec4 aColor;
+uniform mat4 viewProjection;
+out vec4 color;
+void main()
+{
+    gl_Position = viewProjection * vec4(aPos, 1.0);
+    color = aColor;
+}
+"#;
+const DEBUG_FS: &str = r#"#version 330
+in vec4 color;
+out vec4 FragColor;
+void main()
+{
+    FragColor = color;
+}
+"#;
+
+struct DebugLines {
+    program: NativeProgram,
+    vao: NativeVertexArray,
+    vbo: NativeBuffer,
+}
 
 pub struct Graphics {
-    pub gl: glow::Context,
-    aspect_ratio: f32,
+    pub gl: Arc<glow::Context>,
+    camera: Cell<Camera>,
+    render_queue: RefCell<RenderQueue>,
+    debug_lines: DebugLines,
+    text: RefCell<TextRenderer>,
+    ui: RefCell<UiOverlay>,
+    screen_size: Cell<Vec2>,
+    frame_stats: Cell<FrameStats>,
+    last_stats: Cell<FrameStats>,
+    gpu_timer: RefCell<Option<GpuTimer>>,
+    stats_overlay: Cell<bool>,
 }
 
 impl Graphics {
     pub fn new(app: &app::App) -> Graphics {
         unsafe {
-            let gl = glow::Context::from_loader_function(|s| {
+            let mut gl = glow::Context::from_loader_function(|s| {
                 app.video().gl_get_proc_address(s) as *const _
             });
+            ::log::info!(
+                "OpenGL {} ({})",
+                gl.get_parameter_string(glow::VERSION),
+                gl.get_parameter_string(glow::RENDERER)
+            );
+            crate::logging::install_gl_debug_output(&mut gl);
+            let gl = Arc::new(gl);
+            let render_queue = RenderQueue::new(&gl);
+            let debug_lines = Self::create_debug_lines(&gl);
+            let text = TextRenderer::new(&gl);
+            let ui = UiOverlay::new(Arc::clone(&gl), app.window());
+            let gpu_timer = GpuTimer::new(&gl);
             Self {
                 gl,
-                aspect_ratio: app.window_width as f32 / app.window_height as f32,
+                camera: Cell::new(Camera::new(
+                    app.window_width as f32 / app.window_height as f32,
+                )),
+                render_queue: RefCell::new(render_queue),
+                debug_lines,
+                text: RefCell::new(text),
+                ui: RefCell::new(ui),
+                screen_size: Cell::new(Vec2::new(
+                    app.window_width as f32,
+                    app.window_height as f32,
+                )),
+                frame_stats: Cell::new(FrameStats::default()),
+                last_stats: Cell::new(FrameStats::default()),
+                gpu_timer: RefCell::new(gpu_timer),
+                stats_overlay: Cell::new(false),
             }
         }
     }
+    pub fn camera(&self) -> Camera {
+        self.camera.get()
+    }
+    pub fn set_camera(&self, camera: Camera) {
+        self.camera.set(camera);
+    }
+    /// Counters and timings of the last completed frame.
+    pub fn stats(&self) -> FrameStats {
+        self.last_stats.get()
+    }
+    pub fn set_stats_overlay(&self, visible: bool) {
+        self.stats_overlay.set(visible);
+    }
+    pub fn stats_overlay(&self) -> bool {
+        self.stats_overlay.get()
+    }
+    pub(crate) fn count(&self, f: impl FnOnce(&mut FrameStats)) {
+        let mut frame_stats = self.frame_stats.get();
+        f(&mut frame_stats);
+        self.frame_stats.set(frame_stats);
+    }
+    pub fn ui(&self) -> std::cell::Ref<'_, UiOverlay> {
+        self.ui.borrow()
+    }
+    /// Draws `text` with its top-left corner at `position`, in pixels from the
+    /// top-left of the window.
+    pub fn draw_text(&self, font: &Font, text: &str, position: Vec2, px: f32, color: Vec4) {
+        self.text
+            .borrow_mut()
+            .queue_screen(font, text, position, px, None, color);
+    }
+    /// Like `draw_text`, wrapping lines that would get wider than `max_width`.
+    pub fn draw_text_wrapped(
+        &self,
+        font: &Font,
+        text: &str,
+        position: Vec2,
+        px: f32,
+        max_width: f32,
+        color: Vec4,
+    ) {
+        self.text
+            .borrow_mut()
+            .queue_screen(font, text, position, px, Some(max_width), color);
+    }
+    /// Draws `text` in the XY plane of `transform`, e.g. a node's world transform.
+    pub fn draw_text_world(
+        &self,
+        font: &Font,
+        text: &str,
+        transform: &Mat4,
+        size: f32,
+        color: Vec4,
+    ) {
+        self.text
+            .borrow_mut()
+            .queue_world(font, text, transform, size, color);
+    }
+    pub fn handle_event(&self, event: &Event) {
+        self.ui.borrow_mut().handle_event(event);
+    }
+    /// Opens the UI pass; call before updating so behaviors can build panels.
     pub fn begin_frame(&self) {
+        self.ui.borrow_mut().begin();
+    }
+    /// Clears the framebuffer and starts the GPU timer; call right before
+    /// drawing so the timer doesn't include the update.
+    pub fn begin_draw(&self) {
         unsafe {
             self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
+            // Clears only touch what the masks allow.
+            self.gl.depth_mask(true);
+            self.gl.stencil_mask(0xff);
             self.gl
-                .clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
-            self.gl.disable(glow::CULL_FACE);
+                .clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
+        }
+        // The overlay leaves blending on and depth testing off.
+        RenderState::default().apply(&self.gl, None);
+        if let Some(timer) = self.gpu_timer.borrow_mut().as_mut() {
+            timer.begin(&self.gl);
         }
     }
 
-    pub fn draw_mesh(&self, transform: &Mat4, shader: &crate::shader::Shader, mesh: &Mesh) {
-        unsafe {
-            for (i, texture) in mesh.texture.iter().enumerate() {
-                self.gl.active_texture(glow::TEXTURE0 + i as u32);
-                let texture_type_str = match texture.texture_type {
-                    TextureType::DIFFUSE(_) => "texture_diffuse",
-                    TextureType::SPECULAR(_) => "texture_specular",
-                };
+    /// Queues `mesh` to be drawn with `shader` and no material uniforms.
+    pub fn draw_mesh(&self, transform: &Mat4, shader: &crate::shader::Shader, mesh: &GpuMesh) {
+        let item = DrawItem::new(
+            shader.program(),
+            0,
+            mesh,
+            *transform,
+            RenderState::default(),
+        );
+        self.render_queue.borrow_mut().push(item, false);
+    }
+    /// Queues `mesh` to be drawn with `material`. Opaque items are sorted to
+    /// share program and vertex array binds, transparent ones back to front.
+    /// Materials whose shader or textures are still loading are skipped.
+    pub fn submit(&self, mesh: &GpuMesh, material: &Material, transform: &Mat4) {
+        if !material.is_ready() {
+            return;
+        }
+        let mut queue = self.render_queue.borrow_mut();
+        queue.push_material(material);
+        queue.push(
+            DrawItem::from_material(mesh, material, *transform),
+            material.is_transparent(),
+        );
+    }
+    /// Queues one submesh of `mesh`, so each piece can have its own material.
+    pub fn submit_submesh(
+        &self,
+        mesh: &GpuMesh,
+        submesh: usize,
+        material: &Material,
+        transform: &Mat4,
+    ) {
+        if !material.is_ready() {
+            return;
+        }
+        let Some(&Submesh {
+            first_index,
+            index_count,
+        }) = mesh.submeshes.get(submesh)
+        else {
+            ::log::warn!(
+                "submesh {submesh} out of range for a mesh with {}",
+                mesh.submeshes.len()
+            );
+            return;
+        };
+        let mut item = DrawItem::from_material(mesh, material, *transform);
+        (item.first_index, item.index_count) = (first_index, index_count as i32);
+        let mut queue = self.render_queue.borrow_mut();
+        queue.push_material(material);
+        queue.push(item, material.is_transparent());
+    }
+    /// Queues the level of `node`'s `LodGroup` that matches its size on screen
+    /// from the current camera, measured with the first level's bounds.
+    /// While two levels cross-fade both are queued, each dithered away where
+    /// the other is drawn; shaders do that with the `lodFade` uniform.
+    /// Levels whose mesh is still loading are skipped.
+    pub fn submit_lod(&self, node: &GameObject, material: &Material) {
+        let Some(group) = node.lod_group() else {
+            return;
+        };
+        let Some(first) = group.levels.first().and_then(|level| level.mesh.get()) else {
+            return;
+        };
+        if !material.is_ready() {
+            return;
+        }
+        let transform = node.get_world_transform();
+        let sphere = first.sphere.transformed(&transform);
+        let size = LodGroup::screen_size(&sphere, &self.camera.get());
+        let Some(selection) = group.select(size) else {
+            return;
+        };
+        let mut queue = self.render_queue.borrow_mut();
+        queue.push_material(material);
+        let fading = selection.blend < 1.0;
+        let mut push = |level: usize, fade: f32| {
+            let Some(mesh) = group.levels[level].mesh.get() else {
+                return;
+            };
+            let mut item = DrawItem::from_material(mesh, material, transform);
+            item.fade = fade;
+            queue.push(item, material.is_transparent());
+        };
+        push(selection.level, if fading { selection.blend } else { 0.0 });
+        if let (true, Some(next)) = (fading, selection.next) {
+            push(next, -selection.blend);
+        }
+    }
+    /// Queues one instanced draw of `mesh` per entry in `transforms`, whether
+    /// or not automatic instancing is enabled.
+    pub fn submit_instanced(&self, mesh: &GpuMesh, material: &Material, transforms: &[Mat4]) {
+        if !material.is_ready() {
+            return;
+        }
+        let first = transforms.first().copied().unwrap_or(Mat4::IDENTITY);
+        let mut queue = self.render_queue.borrow_mut();
+        queue.push_material(material);
+        queue.push_instanced(
+            DrawItem::from_material(mesh, material, first),
+            transforms,
+            material.is_transparent(),
+        );
+    }
+    /// Whether queued draws sharing mesh and material are merged into
+    /// instanced draws, for shaders that read `aInstanceModel`. On by default.
+    pub fn set_instancing(&self, enabled: bool) {
+        self.render_queue.borrow_mut().instancing = enabled;
+    }
+    pub fn instancing(&self) -> bool {
+        self.render_queue.borrow().instancing
+    }
+    /// Whether queued draws outside the camera frustum are skipped. On by default.
+    pub fn set_culling(&self, enabled: bool) {
+        self.render_queue.borrow_mut().culling = enabled;
+    }
+    pub fn culling(&self) -> bool {
+        self.render_queue.borrow().culling
+    }
+    pub fn end_frame(&self, window: &sdl2::video::Window) {
+        let _span = trace_span!("Graphics::end_frame");
+        stats::time(Phase::Render, || {
+            let mut frame_stats = self.frame_stats.get();
+            {
+                let _span = trace_span!("RenderQueue::flush");
+                self.render_queue.borrow_mut().flush(
+                    &self.gl,
+                    &self.camera.get(),
+                    &mut frame_stats,
+                );
             }
-            self.gl.bind_vertex_array(Some(mesh.vao));
-            let camera_position = Vec3::new(0.0, 0.0, 5.0);
-            let target = Vec3::new(0.0, 0.0, 0.0);
-            let up = Vec3::new(0.0, 1.0, 0.0);
+            self.frame_stats.set(frame_stats);
+            self.draw_debug_lines();
+            let mut frame_stats = self.frame_stats.get();
+            self.text.borrow_mut().flush(
+                &self.gl,
+                &self.camera.get().view_projection(),
+                self.screen_size.get(),
+                &mut frame_stats,
+            );
+            self.frame_stats.set(frame_stats);
+            if self.stats_overlay.get() {
+                let ctx = self.ui.borrow().context().clone();
+                self.last_stats.get().show(&ctx);
+            }
+            self.ui.borrow_mut().end(window);
+        });
+        let mut frame_stats = self.frame_stats.take();
+        if let Some(timer) = self.gpu_timer.borrow_mut().as_mut() {
+            frame_stats.gpu_time = timer.end(&self.gl);
+        }
+        frame_stats.frame = self.last_stats.get().frame + 1;
+        frame_stats.take_cpu_times();
+        self.last_stats.set(frame_stats);
 
-            let projection =
-                Mat4::perspective_rh_gl(45.0_f32.to_radians(), self.aspect_ratio, 0.1, 100.0);
-            let view = Mat4::look_at_rh(camera_position, target, up);
-            shader.set_uniform_mat4_f32("model", transform);
-            shader.set_uniform_mat4_f32("projection", &projection);
-            shader.set_uniform_mat4_f32("view", &view);
-            self.gl.draw_elements(
-                glow::TRIANGLES,
-                mesh.indices.len() as i32,
-                glow::UNSIGNED_INT,
+        window.gl_swap_window();
+        let (width, height) = window.drawable_size();
+        self.screen_size.set(Vec2::new(width as f32, height as f32));
+    }
+    fn create_debug_lines(gl: &glow::Context) -> DebugLines {
+        unsafe {
+            let program = crate::shader::Shader::create_program(gl, DEBUG_VS, DEBUG_FS)
+                .expect("built-in debug line shader");
+            let vao = gl.create_vertex_array().unwrap();
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_vertex_array(Some(vao));
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.enable_vertex_attrib_array(0);
+            gl.vertex_attrib_pointer_f32(
                 0,
+                3,
+                glow::FLOAT,
+                false,
+                size_of::<DebugVertex>() as i32,
+                offset_of!(DebugVertex, position) as i32,
             );
-
-            self.gl.bind_vertex_array(None);
+            gl.enable_vertex_attrib_array(1);
+            gl.vertex_attrib_pointer_f32(
+                1,
+                4,
+                glow::FLOAT,
+                false,
+                size_of::<DebugVertex>() as i32,
+                offset_of!(DebugVertex, color) as i32,
+            );
+            gl.bind_vertex_array(None);
+            DebugLines { program, vao, vbo }
         }
     }
-    pub fn end_frame(&self, window: &sdl2::video::Window) {
-        window.gl_swap_window();
+    /// Draws everything queued through `debug` this frame in one call.
+    fn draw_debug_lines(&self) {
+        let lines = debug::take_lines();
+        if lines.is_empty() {
+            return;
+        }
+        unsafe {
+            let vertices_u8: &[u8] = core::slice::from_raw_parts(
+                lines.as_ptr() as *const u8,
+                std::mem::size_of_val(lines.as_slice()),
+            );
+            let DebugLines { program, vao, vbo } = self.debug_lines;
+            self.gl.use_program(Some(program));
+            let location = self.gl.get_uniform_location(program, "viewProjection");
+            self.gl.uniform_matrix_4_f32_slice(
+                location.as_ref(),
+                false,
+                &self.camera.get().view_projection().to_cols_array(),
+            );
+            self.gl.bind_vertex_array(Some(vao));
+            self.gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            self.gl
+                .buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices_u8, glow::STREAM_DRAW);
+            self.gl.draw_arrays(glow::LINES, 0, lines.len() as i32);
+            self.gl.bind_vertex_array(None);
+            self.gl.use_program(None);
+        }
+        self.count(|stats| {
+            stats.program_binds += 1;
+            stats.state_changes += 1;
+            stats.draw(0);
+        });
     }
     pub fn create_vertex_buffer(
         &self,
         vertices: &[Vertex],
         indices: &[u32],
+    ) -> (NativeVertexArray, NativeBuffer, NativeBuffer) {
+        self.create_vertex_buffer_with_layout(
+            &Vertex::layout(),
+            vertex_bytes(vertices),
+            indices,
+            BufferUsage::Static,
+        )
+    }
+    /// Uploads interleaved `vertices` described by `layout`.
+    pub fn create_vertex_buffer_with_layout(
+        &self,
+        layout: &VertexLayout,
+        vertices: &[u8],
+        indices: &[u32],
+        usage: BufferUsage,
+    ) -> (NativeVertexArray, NativeBuffer, NativeBuffer) {
+        self.upload_buffers(layout, vertices, crate::mesh::index_bytes(indices), usage)
+    }
+    /// Like `create_vertex_buffer_with_layout` with indices of any width.
+    pub(crate) fn upload_buffers(
+        &self,
+        layout: &VertexLayout,
+        vertices: &[u8],
+        indices: &[u8],
+        usage: BufferUsage,
     ) -> (NativeVertexArray, NativeBuffer, NativeBuffer) {
         unsafe {
-            let vertices_u8: &[u8] = core::slice::from_raw_parts(
-                vertices.as_ptr() as *const u8,
-                vertices.len() * core::mem::size_of::<Vertex>(),
-            );
-            let indices_u8: &[u8] = core::slice::from_raw_parts(
-                indices.as_ptr() as *const u8,
-                indices.len() * core::mem::size_of::<u32>(),
-            );
-
             // We construct a buffer and upload the data
             let vbo = self.gl.create_buffer().unwrap();
             let vao = self.gl.create_vertex_array().unwrap();
@@ -83,138 +434,35 @@ impl Graphics {
             self.gl.bind_vertex_array(Some(vao));
             self.gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
             self.gl
-                .buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices_u8, glow::STATIC_DRAW);
+                .buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices, usage.gl());
             self.gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
             self.gl
-                .buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, indices_u8, glow::STATIC_DRAW);
-
-            self.gl.enable_vertex_attrib_array(0);
-
-            self.gl.vertex_attrib_pointer_f32(
-                0,
-                3,
-                glow::FLOAT,
-                false,
-                size_of::<Vertex>() as i32,
-                offset_of!(Vertex, position) as i32,
-            );
-
-            self.gl.enable_vertex_attrib_array(1);
-            //
-            self.gl.vertex_attrib_pointer_f32(
-                1,
-                3,
-                glow::FLOAT,
-                false,
-                size_of::<Vertex>() as i32,
-                offset_of!(Vertex, normal) as i32,
-            );
-            //
-            self.gl.enable_vertex_attrib_array(2);
-            //
-            self.gl.vertex_attrib_pointer_f32(
-                2,
-                2,
-                glow::FLOAT,
-                false,
-                size_of::<Vertex>() as i32,
-                offset_of!(Vertex, uv) as i32,
-            );
+                .buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, indices, usage.gl());
+            layout.apply(&self.gl);
 
             self.gl.bind_vertex_array(None);
 
             (vao, vbo, ebo)
         }
     }
-    fn create_triangle<'a>(&self) -> Mesh {
-        // Define vertices of the triangle
-        let vertices = vec![
-            Vertex {
-                position: Vec3::new(0.0, 0.5, 0.0), // Top vertex
-                normal: Vec3::new(0.0, 0.0, 1.0),   // Normal pointing forward
-                uv: Vec2::new(0.5, 1.0),            // UV for the top vertex
-            },
-            Vertex {
-                position: Vec3::new(-0.5, -0.5, 0.0), // Bottom-left vertex
-                normal: Vec3::new(0.0, 0.0, 1.0),     // Normal pointing forward
-                uv: Vec2::new(0.0, 0.0),              // UV for the bottom-left vertex
-            },
-            Vertex {
-                position: Vec3::new(0.5, -0.5, 0.0), // Bottom-right vertex
-                normal: Vec3::new(0.0, 0.0, 1.0),    // Normal pointing forward
-                uv: Vec2::new(1.0, 0.0),             // UV for the bottom-right vertex
-            },
-        ];
-        let indices = vec![0, 1, 2];
-
-        // No texture for now
-        let texture = vec![];
-
-        // Create and return the Mesh
-        Mesh::new(&self, vertices, indices, texture)
-    }
-    pub fn create_cube<'a>(&self) -> Mesh {
-        // Define vertices of the cube
-        let vertices = vec![
-            // Front face
-            Vertex {
-                position: Vec3::new(-0.5, -0.5, 0.5), // Bottom-left
-                normal: Vec3::new(0.0, 0.0, 1.0),
-                uv: Vec2::new(0.0, 0.0),
-            },
-            Vertex {
-                position: Vec3::new(0.5, -0.5, 0.5), // Bottom-right
-                normal: Vec3::new(0.0, 0.0, 1.0),
-                uv: Vec2::new(1.0, 0.0),
-            },
-            Vertex {
-                position: Vec3::new(0.5, 0.5, 0.5), // Top-right
-                normal: Vec3::new(0.0, 0.0, 1.0),
-                uv: Vec2::new(1.0, 1.0),
-            },
-            Vertex {
-                position: Vec3::new(-0.5, 0.5, 0.5), // Top-left
-                normal: Vec3::new(0.0, 0.0, 1.0),
-                uv: Vec2::new(0.0, 1.0),
-            },
-            // Back face
-            Vertex {
-                position: Vec3::new(-0.5, -0.5, -0.5), // Bottom-left
-                normal: Vec3::new(0.0, 0.0, -1.0),
-                uv: Vec2::new(0.0, 0.0),
-            },
-            Vertex {
-                position: Vec3::new(0.5, -0.5, -0.5), // Bottom-right
-                normal: Vec3::new(0.0, 0.0, -1.0),
-                uv: Vec2::new(1.0, 0.0),
-            },
-            Vertex {
-                position: Vec3::new(0.5, 0.5, -0.5), // Top-right
-                normal: Vec3::new(0.0, 0.0, -1.0),
-                uv: Vec2::new(1.0, 1.0),
-            },
-            Vertex {
-                position: Vec3::new(-0.5, 0.5, -0.5), // Top-left
-                normal: Vec3::new(0.0, 0.0, -1.0),
-                uv: Vec2::new(0.0, 1.0),
-            },
-        ];
-
-        // Define indices for the cube (6 faces, 2 triangles per face)
-        let indices = vec![
-            // Front face
-            0, 1, 2, 2, 3, 0, // Back face
-            4, 5, 6, 6, 7, 4, // Left face
-            4, 0, 3, 3, 7, 4, // Right face
-            1, 5, 6, 6, 2, 1, // Top face
-            3, 2, 6, 6, 7, 3, // Bottom face
-            4, 5, 1, 1, 0, 4,
-        ];
-
-        // No texture for now
-        let texture = vec![];
+    /// A unit cube with per-face normals, see `primitives::cube`.
+    pub fn create_cube(&self) -> GpuMesh {
+        GpuMesh::new(self, &crate::primitives::cube(1.0, 1))
+    }
+}
 
-        // Create and return the Mesh
-        Mesh::new(&self, vertices, indices, texture)
+impl Drop for Graphics {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_program(self.debug_lines.program);
+            self.gl.delete_vertex_array(self.debug_lines.vao);
+            self.gl.delete_buffer(self.debug_lines.vbo);
+        }
+        self.render_queue.get_mut().destroy(&self.gl);
+        self.text.get_mut().destroy(&self.gl);
+        if let Some(timer) = self.gpu_timer.get_mut() {
+            timer.destroy(&self.gl);
+        }
+        self.ui.get_mut().destroy();
     }
 }
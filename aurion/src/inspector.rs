@@ -0,0 +1,153 @@
+//! Scene hierarchy panel for the UI overlay, with editable transforms and
+//! behavior fields.
+use crate::prelude::*;
+use std::ops::RangeInclusive;
+
+/// Visits the fields a type wants to expose for editing.
+pub trait Inspector {
+    fn label(&mut self, name: &str, value: &str);
+    fn bool(&mut self, name: &str, value: &mut bool);
+    fn f32(&mut self, name: &str, value: &mut f32);
+    fn f32_range(&mut self, name: &str, value: &mut f32, range: RangeInclusive<f32>);
+    fn vec3(&mut self, name: &str, value: &mut Vec3);
+}
+
+pub trait Inspect {
+    fn inspect(&mut self, inspector: &mut dyn Inspector);
+}
+
+pub struct EguiInspector<'a> {
+    pub ui: &'a mut egui::Ui,
+}
+impl Inspector for EguiInspector<'_> {
+    fn label(&mut self, name: &str, value: &str) {
+        self.ui.label(format!("{name}: {value}"));
+    }
+    fn bool(&mut self, name: &str, value: &mut bool) {
+        self.ui.checkbox(value, name);
+    }
+    fn f32(&mut self, name: &str, value: &mut f32) {
+        self.ui.horizontal(|ui| {
+            ui.label(name);
+            ui.add(egui::DragValue::new(value).speed(0.01));
+        });
+    }
+    fn f32_range(&mut self, name: &str, value: &mut f32, range: RangeInclusive<f32>) {
+        self.ui.add(egui::Slider::new(value, range).text(name));
+    }
+    fn vec3(&mut self, name: &str, value: &mut Vec3) {
+        self.ui.horizontal(|ui| {
+            ui.label(name);
+            ui.add(egui::DragValue::new(&mut value.x).speed(0.01).prefix("x "));
+            ui.add(egui::DragValue::new(&mut value.y).speed(0.01).prefix("y "));
+            ui.add(egui::DragValue::new(&mut value.z).speed(0.01).prefix("z "));
+        });
+    }
+}
+
+pub struct SceneInspector {
+    selected: Option<GameObject>,
+    pub open: bool,
+}
+impl Default for SceneInspector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl SceneInspector {
+    pub fn new() -> Self {
+        Self {
+            selected: None,
+            open: true,
+        }
+    }
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+    pub fn selected(&self) -> Option<&GameObject> {
+        self.selected.as_ref()
+    }
+    pub fn show(&mut self, ctx: &egui::Context, root: &GameObject) {
+        if !self.open {
+            return;
+        }
+        egui::SidePanel::left("scene_hierarchy").show(ctx, |ui| {
+            ui.heading("Scene");
+            egui::ScrollArea::vertical().show(ui, |ui| self.tree(ui, root));
+        });
+        let Some(node) = self.selected.clone() else {
+            return;
+        };
+        egui::SidePanel::right("scene_inspector").show(ctx, |ui| {
+            ui.heading(node.get_name());
+            ui.separator();
+            Self::transform(ui, &node);
+            ui.separator();
+            for (index, behavior) in node.behaviors().iter().enumerate() {
+                let mut behavior = behavior.borrow_mut();
+                let mut enabled = node.is_behavior_enabled(index);
+                let id = ui.make_persistent_id(("behavior", index));
+                egui::collapsing_header::CollapsingState::load_with_default_open(
+                    ui.ctx(),
+                    id,
+                    true,
+                )
+                .show_header(ui, |ui| {
+                    if ui.checkbox(&mut enabled, behavior.name()).changed() {
+                        node.set_behavior_enabled(index, enabled);
+                    }
+                })
+                .body(|ui| match behavior.inspect() {
+                    Some(fields) => fields.inspect(&mut EguiInspector { ui }),
+                    None => {
+                        ui.weak("no inspectable fields");
+                    }
+                });
+            }
+        });
+    }
+    fn tree(&mut self, ui: &mut egui::Ui, node: &GameObject) {
+        let children = node.children();
+        let selected = self.selected.as_ref().is_some_and(|s| s.ptr_eq(node));
+        if children.is_empty() {
+            if ui.selectable_label(selected, node.get_name()).clicked() {
+                self.selected = Some(node.clone());
+            }
+            return;
+        }
+        let id = ui.make_persistent_id(std::rc::Rc::as_ptr(&node.0) as usize);
+        egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, true)
+            .show_header(ui, |ui| {
+                if ui.selectable_label(selected, node.get_name()).clicked() {
+                    self.selected = Some(node.clone());
+                }
+            })
+            .body(|ui| {
+                for child in &children {
+                    self.tree(ui, child);
+                }
+            });
+    }
+    fn transform(ui: &mut egui::Ui, node: &GameObject) {
+        let (mut scale, rotation, mut translation) =
+            node.get_local_transform().to_scale_rotation_translation();
+        let (y, x, z) = rotation.to_euler(EulerRot::YXZ);
+        let mut euler = Vec3::new(x, y, z) * (180.0 / std::f32::consts::PI);
+        let before = (scale, euler, translation);
+
+        let mut inspector = EguiInspector { ui };
+        inspector.vec3("position", &mut translation);
+        inspector.vec3("rotation", &mut euler);
+        inspector.vec3("scale", &mut scale);
+
+        if before != (scale, euler, translation) {
+            let euler = euler * (std::f32::consts::PI / 180.0);
+            let rotation = Quat::from_euler(EulerRot::YXZ, euler.y, euler.x, euler.z);
+            node.set_local_transform(Mat4::from_scale_rotation_translation(
+                scale,
+                rotation,
+                translation,
+            ));
+        }
+    }
+}
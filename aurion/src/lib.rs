@@ -1,16 +1,64 @@
 mod app;
+mod assets;
+mod bounds;
+mod camera;
+pub mod debug;
+mod game;
 mod graphics;
+mod inspector;
+mod lod;
+pub mod logging;
+mod material;
 mod mesh;
+mod mesh_file;
+mod pak;
+pub mod primitives;
+pub mod processing;
+mod render_queue;
+mod render_state;
 mod scene_graph;
 mod shader;
+pub mod stats;
+mod text;
+mod texture;
+mod time;
+pub mod trace;
+pub mod ui;
 mod utils;
+mod vertex;
+mod vfs;
+pub use egui;
+pub use log;
 pub mod prelude {
     pub use crate::utils::*;
     pub use crate::app::*;
+    pub use crate::assets::{Asset, AssetId, Assets, Handle, LoadContext, LoadState, Loaded};
+    pub use crate::bounds::*;
+    pub use crate::camera::Camera;
+    pub use crate::debug;
+    pub use crate::game::*;
     pub use crate::graphics::*;
+    pub use crate::inspector::*;
+    pub use crate::lod::{LodGroup, LodLevel, LodSelection};
+    pub use crate::logging::LogConsole;
+    pub use crate::material::{Material, UniformValue};
     pub use crate::mesh::*;
+    pub use crate::mesh_file::{MappedMeshFile, MeshFile};
+    pub use crate::pak::{PakCompression, PakEntry, PakMount, PakWriter};
+    pub use crate::primitives;
+    pub use crate::processing;
+    pub use crate::render_state::{
+        BlendMode, Compare, CullMode, PolygonMode, RenderState, StencilOp, StencilState,
+    };
     pub use crate::scene_graph::*;
     pub use crate::shader::Shader;
+    pub use crate::stats::{FrameStats, Phase};
+    pub use crate::text::{Font, PositionedGlyph};
+    pub use crate::texture::{Texture, TextureData};
+    pub use crate::time::Time;
+    pub use crate::ui;
+    pub use crate::vertex::*;
+    pub use crate::vfs::{DirMount, Mount, Vfs, ZipMount};
     pub use glam::*;
     pub use glow::*;
     pub use sdl2::video::GLContext;
@@ -18,4 +66,3 @@ pub mod prelude {
     pub use std::io::Read;
     pub use std::time::Instant;
 }
-
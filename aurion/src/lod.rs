@@ -0,0 +1,132 @@
+//! Level of detail. A `LodGroup` attached to a node lists meshes from most to
+//! least detailed, each with the smallest screen size it's used at, and
+//! `Graphics::submit_lod` draws the one matching the node's current size on
+//! screen, cross-fading between levels with a dither if asked to.
+use crate::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LodLevel {
+    pub mesh: Handle<GpuMesh>,
+    /// Fraction of the screen height the bounds must cover more than for this
+    /// level to be used. At or below the last level's, the node isn't drawn.
+    pub screen_size: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LodGroup {
+    /// Most detailed first, with decreasing `screen_size`.
+    pub levels: Vec<LodLevel>,
+    /// Width of the cross-fade above each threshold, as a fraction of it. 0
+    /// switches levels instantly.
+    pub fade: f32,
+}
+
+/// The levels to draw this frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodSelection {
+    pub level: usize,
+    /// How much of `level` is still drawn while fading into the next, from 1
+    /// (all of it) down to 0.
+    pub blend: f32,
+    /// The level being faded into, or `None` when fading out entirely.
+    pub next: Option<usize>,
+}
+
+impl LodGroup {
+    pub fn new(levels: Vec<LodLevel>) -> Self {
+        Self { levels, fade: 0.0 }
+    }
+    pub fn with_fade(mut self, fade: f32) -> Self {
+        self.fade = fade.max(0.0);
+        self
+    }
+    /// Fraction of the screen height `sphere` covers when seen by `camera`.
+    pub fn screen_size(sphere: &BoundingSphere, camera: &Camera) -> f32 {
+        let distance = sphere.center.distance(camera.position);
+        if distance <= sphere.radius {
+            return f32::INFINITY;
+        }
+        sphere.radius / (distance * (camera.fov_y * 0.5).tan())
+    }
+    /// Picks the level for `screen_size`, or `None` if it's too small to draw.
+    pub fn select(&self, screen_size: f32) -> Option<LodSelection> {
+        let level = self
+            .levels
+            .iter()
+            .position(|level| screen_size > level.screen_size)?;
+        let threshold = self.levels[level].screen_size;
+        let band = threshold * self.fade;
+        // Above the threshold, so never 0: fully faded out is the next level.
+        let blend = if band > 0.0 {
+            ((screen_size - threshold) / band).min(1.0)
+        } else {
+            1.0
+        };
+        let next = (level + 1 < self.levels.len()).then_some(level + 1);
+        Some(LodSelection { level, blend, next })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(fade: f32) -> LodGroup {
+        let level = |screen_size| LodLevel {
+            mesh: Handle::pending("mesh"),
+            screen_size,
+        };
+        LodGroup::new(vec![level(0.5), level(0.25), level(0.125)]).with_fade(fade)
+    }
+
+    fn selection(level: usize, blend: f32, next: Option<usize>) -> Option<LodSelection> {
+        Some(LodSelection { level, blend, next })
+    }
+
+    #[test]
+    fn select_with_fade() {
+        let group = group(0.5);
+        for (screen_size, expected) in [
+            // Above the fade band.
+            (1.0, selection(0, 1.0, Some(1))),
+            (0.75, selection(0, 1.0, Some(1))),
+            // Inside it.
+            (0.625, selection(0, 0.5, Some(1))),
+            // Exactly on a threshold, the next level is drawn fully.
+            (0.5, selection(1, 1.0, Some(2))),
+            (0.3125, selection(1, 0.5, Some(2))),
+            (0.25, selection(2, 1.0, None)),
+            (0.15625, selection(2, 0.5, None)),
+            // At or below the last threshold.
+            (0.125, None),
+            (0.0, None),
+        ] {
+            assert_eq!(group.select(screen_size), expected, "{screen_size}");
+        }
+    }
+
+    #[test]
+    fn select_without_fade() {
+        let group = group(0.0);
+        for (screen_size, expected) in [
+            (f32::INFINITY, selection(0, 1.0, Some(1))),
+            (0.5001, selection(0, 1.0, Some(1))),
+            (0.5, selection(1, 1.0, Some(2))),
+            (0.2, selection(2, 1.0, None)),
+            (0.125, None),
+        ] {
+            assert_eq!(group.select(screen_size), expected, "{screen_size}");
+        }
+    }
+
+    #[test]
+    fn blend_is_never_zero() {
+        let group = group(0.5);
+        for i in 0..=1000 {
+            let screen_size = i as f32 / 1000.0;
+            if let Some(selection) = group.select(screen_size) {
+                assert!(selection.blend > 0.0, "{screen_size}: {selection:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! `log` backend with per-module levels that can be changed while running,
+//! plus a console panel showing the most recent records.
+use crate::prelude::*;
+use ::log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock, RwLock};
+
+const HISTORY: usize = 512;
+
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+pub struct Logger {
+    default_level: RwLock<LevelFilter>,
+    /// Module prefixes and their levels, longest prefix wins.
+    levels: RwLock<Vec<(String, LevelFilter)>>,
+    history: Mutex<VecDeque<LogEntry>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Installs the logger, reading initial levels from `AURION_LOG` in the
+/// `info,aurion::graphics=debug` style. Calling it again returns the same logger.
+pub fn init() -> &'static Logger {
+    let mut installed = false;
+    let logger = LOGGER.get_or_init(|| {
+        installed = true;
+        let logger = Logger {
+            default_level: RwLock::new(LevelFilter::Info),
+            levels: RwLock::new(vec![]),
+            history: Mutex::new(VecDeque::with_capacity(HISTORY)),
+        };
+        if let Ok(spec) = std::env::var("AURION_LOG") {
+            logger.parse(&spec);
+        }
+        logger
+    });
+    if installed && ::log::set_logger(logger).is_ok() {
+        ::log::set_max_level(LevelFilter::Trace);
+    }
+    logger
+}
+
+/// The installed logger, if `init` has run.
+pub fn logger() -> Option<&'static Logger> {
+    LOGGER.get()
+}
+
+impl Logger {
+    pub fn set_default_level(&self, level: LevelFilter) {
+        *self.default_level.write().unwrap() = level;
+    }
+    /// Sets the level for `module` and everything below it, e.g. `aurion::shader`.
+    pub fn set_level(&self, module: &str, level: LevelFilter) {
+        let mut levels = self.levels.write().unwrap();
+        match levels.iter_mut().find(|(m, _)| m == module) {
+            Some(entry) => entry.1 = level,
+            None => levels.push((module.to_string(), level)),
+        }
+        levels.sort_by_key(|(m, _)| std::cmp::Reverse(m.len()));
+    }
+    pub fn parse(&self, spec: &str) {
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    if let Ok(level) = level.parse() {
+                        self.set_level(module, level);
+                    }
+                }
+                None => {
+                    if let Ok(level) = directive.parse() {
+                        self.set_default_level(level);
+                    }
+                }
+            }
+        }
+    }
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.levels
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(module, _)| {
+                target
+                    .strip_prefix(module.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .map_or(*self.default_level.read().unwrap(), |(_, level)| *level)
+    }
+    pub fn history(&self) -> Vec<LogEntry> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+    pub fn clear_history(&self) {
+        self.history.lock().unwrap().clear();
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let entry = LogEntry {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+        eprintln!("[{:<5} {}] {}", entry.level, entry.target, entry.message);
+        let mut history = self.history.lock().unwrap();
+        if history.len() == HISTORY {
+            history.pop_front();
+        }
+        history.push_back(entry);
+    }
+    fn flush(&self) {}
+}
+
+/// In-game window over the logger's history.
+pub struct LogConsole {
+    pub open: bool,
+    filter: LevelFilter,
+    module: String,
+}
+impl Default for LogConsole {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl LogConsole {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            filter: LevelFilter::Trace,
+            module: String::new(),
+        }
+    }
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let Some(logger) = logger() else {
+            return;
+        };
+        let mut open = self.open;
+        egui::Window::new("Log")
+            .open(&mut open)
+            .default_size([600.0, 300.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label("show")
+                        .selected_text(self.filter.as_str())
+                        .show_ui(ui, |ui| {
+                            for level in LevelFilter::iter() {
+                                ui.selectable_value(&mut self.filter, level, level.as_str());
+                            }
+                        });
+                    ui.label("module");
+                    ui.text_edit_singleline(&mut self.module);
+                    if !self.module.is_empty() {
+                        let mut level = logger.level_for(&self.module);
+                        egui::ComboBox::from_id_salt("module_level")
+                            .selected_text(level.as_str())
+                            .show_ui(ui, |ui| {
+                                for option in LevelFilter::iter() {
+                                    ui.selectable_value(&mut level, option, option.as_str());
+                                }
+                            });
+                        if level != logger.level_for(&self.module) {
+                            logger.set_level(&self.module, level);
+                        }
+                    }
+                    if ui.button("clear").clicked() {
+                        logger.clear_history();
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for entry in logger.history() {
+                            if entry.level > self.filter {
+                                continue;
+                            }
+                            let color = match entry.level {
+                                Level::Error => egui::Color32::LIGHT_RED,
+                                Level::Warn => egui::Color32::YELLOW,
+                                Level::Info => egui::Color32::LIGHT_GRAY,
+                                Level::Debug | Level::Trace => egui::Color32::GRAY,
+                            };
+                            ui.colored_label(
+                                color,
+                                format!("{:<5} {}: {}", entry.level, entry.target, entry.message),
+                            );
+                        }
+                    });
+            });
+        self.open = open;
+    }
+}
+
+/// Forwards KHR_debug messages to the logger under the `gl` target.
+pub(crate) fn install_gl_debug_output(gl: &mut glow::Context) {
+    let version = gl.version();
+    let supported = (!version.is_embedded && (version.major, version.minor) >= (4, 3))
+        || gl.supported_extensions().contains("GL_KHR_debug");
+    if !supported {
+        ::log::debug!("KHR_debug unavailable, GL debug output disabled");
+        return;
+    }
+    unsafe {
+        gl.enable(glow::DEBUG_OUTPUT);
+        gl.enable(glow::DEBUG_OUTPUT_SYNCHRONOUS);
+        gl.debug_message_callback(|source, kind, id, severity, message| {
+            let level = match severity {
+                glow::DEBUG_SEVERITY_HIGH => Level::Error,
+                glow::DEBUG_SEVERITY_MEDIUM => Level::Warn,
+                glow::DEBUG_SEVERITY_LOW => Level::Info,
+                _ => Level::Debug,
+            };
+            ::log::log!(
+                target: "gl",
+                level,
+                "{message} (source {source:#x}, type {kind:#x}, id {id})"
+            );
+        });
+    }
+}
@@ -0,0 +1,390 @@
+use crate::prelude::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Ids start at 1; 0 is the implicit material of `Graphics::draw_mesh`.
+static NEXT_MATERIAL_ID: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UniformValue {
+    F32(f32),
+    I32(i32),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+    Mat4(Mat4),
+}
+
+/// A shader plus the uniform values and textures it is drawn with. Uniforms a
+/// material doesn't set keep whatever value the program last had, so
+/// materials sharing a shader should set the same names.
+pub struct Material {
+    id: u32,
+    shader: Handle<Shader>,
+    pub render_state: RenderState,
+    uniforms: Vec<(String, UniformValue)>,
+    /// Bound to texture units in order, each unit's index set on its sampler.
+    textures: Vec<(String, Handle<Texture>)>,
+}
+impl Material {
+    pub fn new(shader: impl Into<Handle<Shader>>) -> Self {
+        Self {
+            id: NEXT_MATERIAL_ID.fetch_add(1, Ordering::Relaxed),
+            shader: shader.into(),
+            render_state: RenderState::default(),
+            uniforms: vec![],
+            textures: vec![],
+        }
+    }
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+    /// The shader or its placeholder, `None` while it's loading without one.
+    pub fn shader(&self) -> Option<&Shader> {
+        self.shader.get()
+    }
+    pub fn set(&mut self, name: &str, value: UniformValue) {
+        match self.uniforms.iter_mut().find(|(n, _)| n == name) {
+            Some(uniform) => uniform.1 = value,
+            None => self.uniforms.push((name.to_string(), value)),
+        }
+    }
+    /// Binds `texture` to the `sampler2D` called `name`.
+    pub fn set_texture(&mut self, name: &str, texture: impl Into<Handle<Texture>>) {
+        let texture = texture.into();
+        match self.textures.iter_mut().find(|(n, _)| n == name) {
+            Some(slot) => slot.1 = texture,
+            None => self.textures.push((name.to_string(), texture)),
+        }
+    }
+    /// Transparent materials are drawn after opaque ones, back to front.
+    pub fn is_transparent(&self) -> bool {
+        self.render_state.is_transparent()
+    }
+    pub fn get(&self, name: &str) -> Option<UniformValue> {
+        self.uniforms
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| *value)
+    }
+    pub fn uniforms(&self) -> &[(String, UniformValue)] {
+        &self.uniforms
+    }
+    pub fn textures(&self) -> &[(String, Handle<Texture>)] {
+        &self.textures
+    }
+    /// Whether the shader and textures can be drawn with, loaded or as
+    /// placeholders.
+    pub fn is_ready(&self) -> bool {
+        self.shader.get().is_some()
+            && self
+                .textures
+                .iter()
+                .all(|(_, texture)| texture.get().is_some())
+    }
+}
+
+/// `.mat` text files with one `key = value` per line and `#` comments:
+///
+/// ```text
+/// shader = shaders/lit          # loads shaders/lit.vs and shaders/lit.fs
+/// blend = alpha                 # opaque, alpha, premultiplied, additive, multiply
+/// cull = back                   # none, back, front
+/// depth_test = less_equal       # off, or a comparison as for stencil_compare
+/// depth_write = false
+/// polygon = line                # fill, line, point
+/// stencil = on                  # on, off; any stencil_* key also turns it on
+/// stencil_compare = equal       # never, less, less_equal, equal, not_equal,
+///                               # greater_equal, greater, always
+/// stencil_ref = 1
+/// stencil_read_mask = 0xff
+/// stencil_write_mask = 0xff
+/// stencil_fail = keep           # keep, zero, replace, increment,
+/// stencil_depth_fail = keep     # increment_wrap, decrement, decrement_wrap,
+/// stencil_pass = replace        # invert
+/// texture albedo = crate.png    # sampler2D albedo
+/// tint = 1 0.5 0.5 1            # 1 to 4 floats, or 16 for a mat4
+/// ```
+impl Asset for Material {
+    type Decoded = MaterialFile;
+    fn decode(vfs: &Vfs, path: &str) -> Result<Self::Decoded, String> {
+        MaterialFile::parse(&vfs.read_to_string(path)?)
+    }
+    fn upload(context: &LoadContext, file: Self::Decoded) -> Result<Self, String> {
+        let mut material = Material::new(context.load(&file.shader.ok_or("no shader")?)?);
+        material.render_state = file.render_state;
+        for (name, value) in file.uniforms {
+            material.set(&name, value);
+        }
+        for (name, path) in file.textures {
+            material.set_texture(&name, context.load(&path)?);
+        }
+        Ok(material)
+    }
+}
+
+/// A parsed material file, with its shader and textures still as paths.
+#[derive(Debug, Default)]
+pub struct MaterialFile {
+    shader: Option<String>,
+    render_state: RenderState,
+    uniforms: Vec<(String, UniformValue)>,
+    textures: Vec<(String, String)>,
+}
+impl MaterialFile {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut file = MaterialFile::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {message}", number + 1);
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| error("expected `key = value`"))?;
+            let render_state = &mut file.render_state;
+            let compare = || parse_compare(value).ok_or_else(|| error("unknown comparison"));
+            match key.split_whitespace().collect::<Vec<_>>()[..] {
+                ["shader"] => file.shader = Some(value.to_string()),
+                ["blend"] => {
+                    render_state.blend = match value {
+                        "opaque" => BlendMode::Opaque,
+                        "alpha" => BlendMode::Alpha,
+                        "premultiplied" => BlendMode::Premultiplied,
+                        "additive" => BlendMode::Additive,
+                        "multiply" => BlendMode::Multiply,
+                        _ => return Err(error("unknown blend mode")),
+                    }
+                }
+                ["cull"] => {
+                    render_state.cull = match value {
+                        "none" => CullMode::None,
+                        "back" => CullMode::Back,
+                        "front" => CullMode::Front,
+                        _ => return Err(error("unknown cull mode")),
+                    }
+                }
+                ["depth_test"] => {
+                    render_state.depth_test = match value {
+                        "off" => None,
+                        _ => Some(compare()?),
+                    }
+                }
+                ["depth_write"] => {
+                    render_state.depth_write =
+                        value.parse().map_err(|_| error("expected true or false"))?
+                }
+                ["polygon"] => {
+                    render_state.polygon = match value {
+                        "fill" => PolygonMode::Fill,
+                        "line" => PolygonMode::Line,
+                        "point" => PolygonMode::Point,
+                        _ => return Err(error("unknown polygon mode")),
+                    }
+                }
+                ["stencil"] => {
+                    render_state.stencil = match value {
+                        "off" => None,
+                        "on" => Some(render_state.stencil.unwrap_or_default()),
+                        _ => return Err(error("expected on or off")),
+                    }
+                }
+                [key] if key.starts_with("stencil_") => {
+                    let stencil = render_state.stencil.get_or_insert_with(Default::default);
+                    let op = || parse_stencil_op(value).ok_or_else(|| error("unknown stencil op"));
+                    let mask = || parse_mask(value).ok_or_else(|| error("expected a mask"));
+                    match key {
+                        "stencil_compare" => stencil.compare = compare()?,
+                        "stencil_ref" => {
+                            stencil.reference =
+                                value.parse().map_err(|_| error("expected an integer"))?
+                        }
+                        "stencil_read_mask" => stencil.read_mask = mask()?,
+                        "stencil_write_mask" => stencil.write_mask = mask()?,
+                        "stencil_fail" => stencil.fail = op()?,
+                        "stencil_depth_fail" => stencil.depth_fail = op()?,
+                        "stencil_pass" => stencil.pass = op()?,
+                        _ => return Err(error("unknown stencil key")),
+                    }
+                }
+                ["texture", name] => file.textures.push((name.to_string(), value.to_string())),
+                [name] => {
+                    let floats = value
+                        .split_whitespace()
+                        .map(str::parse)
+                        .collect::<Result<Vec<f32>, _>>()
+                        .map_err(|_| error("expected numbers"))?;
+                    let value = match floats[..] {
+                        [x] => UniformValue::F32(x),
+                        [x, y] => UniformValue::Vec2(Vec2::new(x, y)),
+                        [x, y, z] => UniformValue::Vec3(Vec3::new(x, y, z)),
+                        [x, y, z, w] => UniformValue::Vec4(Vec4::new(x, y, z, w)),
+                        _ if floats.len() == 16 => {
+                            UniformValue::Mat4(Mat4::from_cols_slice(&floats))
+                        }
+                        _ => return Err(error("expected 1 to 4 or 16 numbers")),
+                    };
+                    file.uniforms.push((name.to_string(), value));
+                }
+                _ => return Err(error("unknown key")),
+            }
+        }
+        Ok(file)
+    }
+}
+
+fn parse_compare(value: &str) -> Option<Compare> {
+    Some(match value {
+        "never" => Compare::Never,
+        "less" => Compare::Less,
+        "less_equal" => Compare::LessEqual,
+        "equal" => Compare::Equal,
+        "not_equal" => Compare::NotEqual,
+        "greater_equal" => Compare::GreaterEqual,
+        "greater" => Compare::Greater,
+        "always" => Compare::Always,
+        _ => return None,
+    })
+}
+
+fn parse_stencil_op(value: &str) -> Option<StencilOp> {
+    Some(match value {
+        "keep" => StencilOp::Keep,
+        "zero" => StencilOp::Zero,
+        "replace" => StencilOp::Replace,
+        "increment" => StencilOp::Increment,
+        "increment_wrap" => StencilOp::IncrementWrap,
+        "decrement" => StencilOp::Decrement,
+        "decrement_wrap" => StencilOp::DecrementWrap,
+        "invert" => StencilOp::Invert,
+        _ => return None,
+    })
+}
+
+/// Decimal or `0x` hexadecimal.
+fn parse_mask(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(text: &str) -> RenderState {
+        MaterialFile::parse(text).unwrap().render_state
+    }
+
+    #[test]
+    fn parses_blend() {
+        assert_eq!(state("blend = opaque").blend, BlendMode::Opaque);
+        assert_eq!(state("blend = alpha").blend, BlendMode::Alpha);
+        assert_eq!(
+            state("blend = premultiplied").blend,
+            BlendMode::Premultiplied
+        );
+        assert_eq!(state("blend = additive").blend, BlendMode::Additive);
+        assert_eq!(state("blend = multiply").blend, BlendMode::Multiply);
+    }
+
+    #[test]
+    fn parses_cull() {
+        assert_eq!(state("cull = none").cull, CullMode::None);
+        assert_eq!(state("cull = back").cull, CullMode::Back);
+        assert_eq!(state("cull = front").cull, CullMode::Front);
+    }
+
+    #[test]
+    fn parses_depth() {
+        assert_eq!(state("depth_test = off").depth_test, None);
+        assert_eq!(
+            state("depth_test = less_equal").depth_test,
+            Some(Compare::LessEqual)
+        );
+        assert_eq!(
+            state("depth_test = always").depth_test,
+            Some(Compare::Always)
+        );
+        assert!(!state("depth_write = false").depth_write);
+        assert!(state("depth_write = true").depth_write);
+    }
+
+    #[test]
+    fn parses_polygon() {
+        assert_eq!(state("polygon = fill").polygon, PolygonMode::Fill);
+        assert_eq!(state("polygon = line").polygon, PolygonMode::Line);
+        assert_eq!(state("polygon = point").polygon, PolygonMode::Point);
+    }
+
+    #[test]
+    fn parses_stencil() {
+        assert_eq!(state("stencil = on").stencil, Some(StencilState::default()));
+        assert_eq!(state("stencil_ref = 2\nstencil = off").stencil, None);
+        let stencil = state(
+            "stencil_compare = not_equal
+             stencil_ref = 3
+             stencil_read_mask = 0x0f
+             stencil_write_mask = 240
+             stencil_fail = zero
+             stencil_depth_fail = decrement_wrap
+             stencil_pass = invert
+             stencil = on",
+        )
+        .stencil;
+        assert_eq!(
+            stencil,
+            Some(StencilState {
+                compare: Compare::NotEqual,
+                reference: 3,
+                read_mask: 0x0f,
+                write_mask: 240,
+                fail: StencilOp::Zero,
+                depth_fail: StencilOp::DecrementWrap,
+                pass: StencilOp::Invert,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_state_values() {
+        for text in [
+            "blend = shiny",
+            "cull = sideways",
+            "depth_test = sometimes",
+            "depth_write = maybe",
+            "polygon = dots",
+            "stencil = yes",
+            "stencil_compare = off",
+            "stencil_ref = one",
+            "stencil_read_mask = 0xgg",
+            "stencil_pass = explode",
+            "stencil_mode = on",
+        ] {
+            let error = MaterialFile::parse(text).unwrap_err();
+            assert!(!error.contains("numbers"), "{text}: {error}");
+        }
+    }
+
+    #[test]
+    fn parses_shader_textures_and_uniforms() {
+        let file = MaterialFile::parse(
+            "shader = shaders/lit # comment
+             texture albedo = crate.png
+             tint = 1 0.5 0.5 1",
+        )
+        .unwrap();
+        assert_eq!(file.shader.as_deref(), Some("shaders/lit"));
+        assert_eq!(file.textures, [("albedo".into(), "crate.png".into())]);
+        assert_eq!(
+            file.uniforms,
+            [(
+                "tint".into(),
+                UniformValue::Vec4(Vec4::new(1.0, 0.5, 0.5, 1.0))
+            )]
+        );
+        assert_eq!(file.render_state, RenderState::default());
+    }
+}
@@ -1,37 +1,398 @@
-use crate::app::{Texture, Vertex};
-use crate::prelude::Graphics;
+//! `MeshData` is plain CPU-side geometry that loaders, generators and
+//! processing work on without a GL context; `GpuMesh` is the uploaded copy the
+//! renderer draws.
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::prelude::{Graphics, Mat3, Mat4};
+use crate::vertex::{vertex_bytes, Vertex, VertexLayout, VertexType};
 use glow::{HasContext, NativeBuffer, NativeVertexArray};
-pub type MeshId = usize;
-pub struct Mesh<'a> {
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// A range of `MeshData::indices` drawn as one piece, usually with its own
+/// material.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
-    pub texture: Vec<Texture>,
+    /// Empty means the whole index buffer is one submesh.
+    pub submeshes: Vec<Submesh>,
+}
+impl MeshData {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        Self {
+            vertices,
+            indices,
+            submeshes: vec![],
+        }
+    }
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+    /// The explicit submeshes, or one covering every index.
+    pub fn submeshes(&self) -> Vec<Submesh> {
+        if self.submeshes.is_empty() {
+            vec![Submesh {
+                first_index: 0,
+                index_count: self.indices.len() as u32,
+            }]
+        } else {
+            self.submeshes.clone()
+        }
+    }
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|vertex| vertex.position))
+    }
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::from_points(self.vertices.iter().map(|vertex| vertex.position))
+    }
+    /// Checks indices and submeshes are in range and form whole triangles.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.indices.len().is_multiple_of(3) {
+            return Err(format!(
+                "{} indices is not a whole number of triangles",
+                self.indices.len()
+            ));
+        }
+        if let Some(index) = self
+            .indices
+            .iter()
+            .find(|&&index| index as usize >= self.vertices.len())
+        {
+            return Err(format!(
+                "index {index} out of range for {} vertices",
+                self.vertices.len()
+            ));
+        }
+        for (i, submesh) in self.submeshes.iter().enumerate() {
+            let end = submesh.first_index as usize + submesh.index_count as usize;
+            if end > self.indices.len() || !submesh.index_count.is_multiple_of(3) {
+                return Err(format!(
+                    "submesh {i} ({submesh:?}) is not a valid index range"
+                ));
+            }
+        }
+        Ok(())
+    }
+    /// Moves positions by `transform` and keeps normals and tangents
+    /// perpendicular to the surface, flipping the winding if it mirrors.
+    pub fn transform(&mut self, transform: &Mat4) {
+        let tangent_matrix = Mat3::from_mat4(*transform);
+        let normal_matrix = tangent_matrix.inverse().transpose();
+        for vertex in &mut self.vertices {
+            vertex.position = transform.transform_point3(vertex.position);
+            vertex.normal = (normal_matrix * vertex.normal).normalize_or_zero();
+            let tangent = (tangent_matrix * vertex.tangent.truncate()).normalize_or_zero();
+            vertex.tangent = tangent.extend(vertex.tangent.w);
+        }
+        if transform.determinant() < 0.0 {
+            self.flip_winding();
+        }
+    }
+    /// Reverses the order of every triangle, turning faces inside out.
+    pub fn flip_winding(&mut self) {
+        for triangle in self.indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+    /// Appends `other` as new submeshes after the existing ones.
+    pub fn append(&mut self, other: &MeshData) {
+        let mut submeshes = if self.indices.is_empty() {
+            vec![]
+        } else {
+            self.submeshes()
+        };
+        let base_vertex = self.vertices.len() as u32;
+        let base_index = self.indices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices
+            .extend(other.indices.iter().map(|index| index + base_vertex));
+        submeshes.extend(other.submeshes().into_iter().map(|submesh| Submesh {
+            first_index: submesh.first_index + base_index,
+            ..submesh
+        }));
+        self.submeshes = submeshes;
+    }
+}
+
+/// How often a `GpuMesh` is expected to change, passed to GL as a hint.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BufferUsage {
+    /// Uploaded once.
+    #[default]
+    Static,
+    /// Updated now and then, e.g. terrain edits.
+    Dynamic,
+    /// Rewritten every frame, e.g. particles.
+    Stream,
+}
+impl BufferUsage {
+    pub(crate) fn gl(self) -> u32 {
+        match self {
+            BufferUsage::Static => glow::STATIC_DRAW,
+            BufferUsage::Dynamic => glow::DYNAMIC_DRAW,
+            BufferUsage::Stream => glow::STREAM_DRAW,
+        }
+    }
+}
+
+/// How indices are stored in a `GpuMesh`'s index buffer.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum IndexFormat {
+    /// Half the size, for meshes of up to 65536 vertices.
+    U16,
+    #[default]
+    U32,
+}
+impl IndexFormat {
+    /// Bytes per index.
+    pub fn size(self) -> u32 {
+        match self {
+            IndexFormat::U16 => 2,
+            IndexFormat::U32 => 4,
+        }
+    }
+    pub(crate) fn gl(self) -> u32 {
+        match self {
+            IndexFormat::U16 => glow::UNSIGNED_SHORT,
+            IndexFormat::U32 => glow::UNSIGNED_INT,
+        }
+    }
+}
+
+/// Vertex and index buffers on the GPU, from `MeshData` or any vertex layout.
+pub struct GpuMesh {
     pub vao: NativeVertexArray,
     pub vbo: NativeBuffer,
     pub ebo: NativeBuffer,
-    gl: &'a glow::Context,
-}
-impl<'a> Mesh<'a> {
-    pub fn new(
-        renderer: &'a Graphics,
-        vertices: Vec<Vertex>,
-        indices: Vec<u32>,
-        texture: Vec<Texture>,
+    pub vertex_count: u32,
+    /// The range of indices drawn when the whole mesh is submitted.
+    pub first_index: u32,
+    pub index_count: u32,
+    pub submeshes: Vec<Submesh>,
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+    pub layout: VertexLayout,
+    /// `U32` unless uploaded from a mesh file with 16-bit indices.
+    pub index_format: IndexFormat,
+    pub usage: BufferUsage,
+    /// Allocated sizes, in vertices and indices.
+    vertex_capacity: u32,
+    index_capacity: u32,
+    gl: Arc<glow::Context>,
+}
+impl GpuMesh {
+    pub fn new(renderer: &Graphics, data: &MeshData) -> Self {
+        let mut mesh = Self::from_vertices(renderer, &data.vertices, &data.indices);
+        mesh.submeshes = data.submeshes();
+        mesh
+    }
+    pub fn from_vertices<V: VertexType>(
+        renderer: &Graphics,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> Self {
+        let bytes = vertex_bytes(vertices);
+        Self::from_bytes(renderer, &V::layout(), bytes, indices, BufferUsage::Static)
+    }
+    /// Uploads interleaved `vertices` described by `layout`. Bounds come from
+    /// its `Float32x3` position attribute, if it has one.
+    pub fn from_bytes(
+        renderer: &Graphics,
+        layout: &VertexLayout,
+        vertices: &[u8],
+        indices: &[u32],
+        usage: BufferUsage,
     ) -> Self {
-        let (vao, vbo, ebo) = renderer.create_vertex_buffer(&vertices, &indices);
+        let positions = layout.positions(vertices);
+        let aabb = Aabb::from_points(positions.clone());
+        let sphere = BoundingSphere::from_points(positions);
+        let indices = (index_bytes(indices), IndexFormat::U32);
+        Self::with_bounds(renderer, layout, vertices, indices, usage, aabb, sphere)
+    }
+    /// Like `from_bytes` with bounds already known and indices already in
+    /// their stored format, e.g. from a cooked file.
+    pub(crate) fn with_bounds(
+        renderer: &Graphics,
+        layout: &VertexLayout,
+        vertices: &[u8],
+        (indices, index_format): (&[u8], IndexFormat),
+        usage: BufferUsage,
+        aabb: Aabb,
+        sphere: BoundingSphere,
+    ) -> Self {
+        let (vao, vbo, ebo) = renderer.upload_buffers(layout, vertices, indices, usage);
+        let vertex_count = vertices.len() as u32 / layout.stride.max(1);
+        let index_count = indices.len() as u32 / index_format.size();
         Self {
-            vertices,
-            indices,
-            texture,
             vao,
             vbo,
             ebo,
-            gl: &renderer.gl,
+            vertex_count,
+            first_index: 0,
+            index_count,
+            submeshes: vec![Submesh {
+                first_index: 0,
+                index_count,
+            }],
+            aabb,
+            sphere,
+            layout: layout.clone(),
+            index_format,
+            usage,
+            vertex_capacity: vertex_count,
+            index_capacity: index_count,
+            gl: Arc::clone(&renderer.gl),
+        }
+    }
+    /// Empty buffers with room for the given number of vertices and indices,
+    /// to be filled with `update_vertices` and `update_indices`.
+    pub fn with_capacity(
+        renderer: &Graphics,
+        layout: &VertexLayout,
+        vertex_capacity: u32,
+        index_capacity: u32,
+        usage: BufferUsage,
+    ) -> Self {
+        let mut mesh = Self::from_bytes(renderer, layout, &[], &[], usage);
+        mesh.resize(vertex_capacity, index_capacity);
+        mesh
+    }
+    pub fn vertex_capacity(&self) -> u32 {
+        self.vertex_capacity
+    }
+    pub fn index_capacity(&self) -> u32 {
+        self.index_capacity
+    }
+    /// Overwrites vertices from `first_vertex` on. The bounds only grow to
+    /// include the new positions; set `aabb` and `sphere` if they shrink.
+    pub fn update_vertices<V: VertexType>(
+        &mut self,
+        first_vertex: u32,
+        vertices: &[V],
+    ) -> Result<(), String> {
+        if V::layout() != self.layout {
+            return Err("vertex type doesn't match the mesh's layout".to_string());
+        }
+        let end = range_end(
+            "vertices",
+            first_vertex,
+            vertices.len(),
+            self.vertex_capacity,
+        )?;
+        let bytes = vertex_bytes(vertices);
+        let offset = first_vertex * self.layout.stride;
+        unsafe { write_buffer(&self.gl, self.vbo, offset, bytes) };
+        self.vertex_count = self.vertex_count.max(end);
+        self.aabb = Aabb::from_points(self.layout.positions(bytes)).union(self.aabb);
+        self.sphere = BoundingSphere::from_aabb(&self.aabb);
+        Ok(())
+    }
+    /// Overwrites indices from `first_index` on, extending `index_count` if
+    /// they go past it. Only for meshes with `U32` indices.
+    pub fn update_indices(&mut self, first_index: u32, indices: &[u32]) -> Result<(), String> {
+        if self.index_format != IndexFormat::U32 {
+            return Err("mesh doesn't have 32-bit indices".to_string());
+        }
+        let end = range_end("indices", first_index, indices.len(), self.index_capacity)?;
+        // Checked against the capacity since vertices may be written after.
+        if let Some(index) = indices.iter().find(|&&index| index >= self.vertex_capacity) {
+            return Err(format!(
+                "index {index} out of range for vertex capacity {}",
+                self.vertex_capacity
+            ));
         }
+        let bytes = index_bytes(indices);
+        unsafe { write_buffer(&self.gl, self.ebo, first_index * 4, bytes) };
+        // Indices before the drawn range don't extend it.
+        self.index_count = self.index_count.max(end.saturating_sub(self.first_index));
+        Ok(())
     }
+    /// Reallocates both buffers, keeping whatever fits of the old contents.
+    pub fn resize(&mut self, vertex_capacity: u32, index_capacity: u32) {
+        let stride = self.layout.stride;
+        unsafe {
+            reallocate(
+                &self.gl,
+                self.vbo,
+                self.vertex_capacity * stride,
+                vertex_capacity * stride,
+                self.usage,
+            );
+            let index_size = self.index_format.size();
+            reallocate(
+                &self.gl,
+                self.ebo,
+                self.index_capacity * index_size,
+                index_capacity * index_size,
+                self.usage,
+            );
+        }
+        self.vertex_capacity = vertex_capacity;
+        self.index_capacity = index_capacity;
+        self.vertex_count = self.vertex_count.min(vertex_capacity);
+        self.first_index = self.first_index.min(index_capacity);
+        self.index_count = self.index_count.min(index_capacity - self.first_index);
+    }
+}
+
+/// End of `len` elements from `first`, if they fit in `capacity`.
+fn range_end(what: &str, first: u32, len: usize, capacity: u32) -> Result<u32, String> {
+    u32::try_from(len)
+        .ok()
+        .and_then(|len| first.checked_add(len))
+        .filter(|&end| end <= capacity)
+        .ok_or_else(|| format!("{len} {what} from {first} out of range for capacity {capacity}"))
+}
+
+/// Writes through `COPY_WRITE_BUFFER` so no vertex array's bindings change.
+unsafe fn write_buffer(gl: &glow::Context, buffer: NativeBuffer, offset: u32, bytes: &[u8]) {
+    gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(buffer));
+    gl.buffer_sub_data_u8_slice(glow::COPY_WRITE_BUFFER, offset as i32, bytes);
+    gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
+}
+
+pub(crate) fn index_bytes(indices: &[u32]) -> &[u8] {
+    unsafe {
+        core::slice::from_raw_parts(
+            indices.as_ptr() as *const u8,
+            std::mem::size_of_val(indices),
+        )
+    }
+}
+
+unsafe fn reallocate(
+    gl: &glow::Context,
+    buffer: NativeBuffer,
+    old_size: u32,
+    new_size: u32,
+    usage: BufferUsage,
+) {
+    let kept = old_size.min(new_size) as i32;
+    let scratch = (kept > 0).then(|| gl.create_buffer().ok()).flatten();
+    if let Some(scratch) = scratch {
+        gl.bind_buffer(glow::COPY_READ_BUFFER, Some(buffer));
+        gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(scratch));
+        gl.buffer_data_size(glow::COPY_WRITE_BUFFER, kept, glow::STREAM_COPY);
+        gl.copy_buffer_sub_data(glow::COPY_READ_BUFFER, glow::COPY_WRITE_BUFFER, 0, 0, kept);
+    }
+    gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(buffer));
+    gl.buffer_data_size(glow::COPY_WRITE_BUFFER, new_size as i32, usage.gl());
+    if let Some(scratch) = scratch {
+        gl.bind_buffer(glow::COPY_READ_BUFFER, Some(scratch));
+        gl.copy_buffer_sub_data(glow::COPY_READ_BUFFER, glow::COPY_WRITE_BUFFER, 0, 0, kept);
+        gl.delete_buffer(scratch);
+    }
+    gl.bind_buffer(glow::COPY_READ_BUFFER, None);
+    gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
 }
 
-impl Drop for Mesh<'_> {
+impl Drop for GpuMesh {
     fn drop(&mut self) {
         unsafe {
             self.gl.delete_vertex_array(self.vao);
@@ -40,3 +401,234 @@ impl Drop for Mesh<'_> {
         }
     }
 }
+
+/// Segments a `StreamMesh` cycles through, so the CPU writes one while the
+/// GPU may still be reading the previous frames'.
+pub const STREAM_SEGMENTS: u32 = 3;
+
+/// Geometry rewritten every frame, such as particles or trails. Each `write`
+/// goes to the next segment of a ring in one set of buffers, and submitting
+/// `mesh()` draws the latest one.
+pub struct StreamMesh<V: VertexType> {
+    mesh: GpuMesh,
+    segment: u32,
+    /// Capacity of one segment, in vertices and indices.
+    vertices_per_segment: u32,
+    indices_per_segment: u32,
+    rebased: Vec<u32>,
+    vertex: PhantomData<V>,
+}
+impl<V: VertexType> StreamMesh<V> {
+    pub fn new(renderer: &Graphics, vertex_capacity: u32, index_capacity: u32) -> Self {
+        let mesh = GpuMesh::with_capacity(
+            renderer,
+            &V::layout(),
+            vertex_capacity * STREAM_SEGMENTS,
+            index_capacity * STREAM_SEGMENTS,
+            BufferUsage::Stream,
+        );
+        Self {
+            mesh,
+            segment: 0,
+            vertices_per_segment: vertex_capacity,
+            indices_per_segment: index_capacity,
+            rebased: vec![],
+            vertex: PhantomData,
+        }
+    }
+    /// Replaces the geometry, growing the buffers if it doesn't fit.
+    pub fn write(&mut self, vertices: &[V], indices: &[u32]) {
+        let (vertex_count, index_count) = (vertices.len() as u32, indices.len() as u32);
+        if vertex_count > self.vertices_per_segment || index_count > self.indices_per_segment {
+            self.vertices_per_segment = vertex_count
+                .max(self.vertices_per_segment)
+                .next_power_of_two();
+            self.indices_per_segment = index_count
+                .max(self.indices_per_segment)
+                .next_power_of_two();
+            self.mesh.resize(
+                self.vertices_per_segment * STREAM_SEGMENTS,
+                self.indices_per_segment * STREAM_SEGMENTS,
+            );
+        }
+        self.segment = (self.segment + 1) % STREAM_SEGMENTS;
+        let first_vertex = self.segment * self.vertices_per_segment;
+        let first_index = self.segment * self.indices_per_segment;
+        self.rebased.clear();
+        self.rebased
+            .extend(indices.iter().map(|index| index + first_vertex));
+        let mesh = &mut self.mesh;
+        unsafe {
+            write_buffer(
+                &mesh.gl,
+                mesh.vbo,
+                first_vertex * mesh.layout.stride,
+                vertex_bytes(vertices),
+            );
+            write_buffer(
+                &mesh.gl,
+                mesh.ebo,
+                first_index * 4,
+                index_bytes(&self.rebased),
+            );
+        }
+        mesh.vertex_count = vertex_count;
+        mesh.first_index = first_index;
+        mesh.index_count = index_count;
+        mesh.submeshes = vec![Submesh {
+            first_index,
+            index_count,
+        }];
+        mesh.aabb = Aabb::from_points(mesh.layout.positions(vertex_bytes(vertices)));
+        mesh.sphere = BoundingSphere::from_aabb(&mesh.aabb);
+    }
+    /// The mesh to submit; draws what the last `write` put in.
+    pub fn mesh(&self) -> &GpuMesh {
+        &self.mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{Vec2, Vec3, Vec4};
+
+    fn vertex(x: f32, y: f32) -> Vertex {
+        Vertex {
+            position: Vec3::new(x, y, 0.0),
+            normal: Vec3::Z,
+            uv: Vec2::new(x, y),
+            tangent: Vec4::new(1.0, 0.0, 0.0, 1.0),
+        }
+    }
+
+    /// A unit quad in the XY plane facing +Z.
+    fn quad() -> MeshData {
+        MeshData::new(
+            vec![
+                vertex(0.0, 0.0),
+                vertex(1.0, 0.0),
+                vertex(1.0, 1.0),
+                vertex(0.0, 1.0),
+            ],
+            vec![0, 1, 2, 0, 2, 3],
+        )
+    }
+
+    #[test]
+    fn validate_rejects_partial_triangles() {
+        let mut mesh = quad();
+        assert!(mesh.validate().is_ok());
+        mesh.indices.pop();
+        assert!(mesh.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_indices() {
+        let mut mesh = quad();
+        mesh.indices[4] = 4;
+        assert!(mesh.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_submeshes() {
+        let mut mesh = quad();
+        mesh.submeshes = vec![Submesh {
+            first_index: 3,
+            index_count: 6,
+        }];
+        assert!(mesh.validate().is_err());
+    }
+
+    #[test]
+    fn submeshes_default_to_every_index() {
+        let mesh = quad();
+        assert_eq!(
+            mesh.submeshes(),
+            [Submesh {
+                first_index: 0,
+                index_count: 6,
+            }]
+        );
+    }
+
+    #[test]
+    fn append_offsets_indices_and_submeshes() {
+        let mut mesh = quad();
+        mesh.append(&quad());
+        assert_eq!(mesh.vertices.len(), 8);
+        assert_eq!(&mesh.indices[6..], [4, 5, 6, 4, 6, 7]);
+        assert_eq!(
+            mesh.submeshes,
+            [
+                Submesh {
+                    first_index: 0,
+                    index_count: 6,
+                },
+                Submesh {
+                    first_index: 6,
+                    index_count: 6,
+                },
+            ]
+        );
+        assert!(mesh.validate().is_ok());
+    }
+
+    #[test]
+    fn append_to_empty_keeps_one_submesh() {
+        let mut mesh = MeshData::default();
+        mesh.append(&quad());
+        assert_eq!(mesh.indices, quad().indices);
+        assert_eq!(mesh.submeshes, quad().submeshes());
+    }
+
+    #[test]
+    fn flip_winding_reverses_triangles() {
+        let mut mesh = quad();
+        mesh.flip_winding();
+        assert_eq!(mesh.indices, [0, 2, 1, 0, 3, 2]);
+    }
+
+    #[test]
+    fn transform_keeps_normals_perpendicular() {
+        // A shear tilts the quad's plane, so transforming normals by the
+        // matrix itself would leave them off the surface.
+        let mut mesh = quad();
+        mesh.vertices.iter_mut().for_each(|v| v.normal = Vec3::X);
+        let mut tilted = mesh.clone();
+        for v in &mut tilted.vertices {
+            v.position = Vec3::new(0.0, v.position.x, v.position.y);
+        }
+        let shear = Mat4::from_cols_array(&[
+            1.0, 0.0, 0.0, 0.0, //
+            1.0, 1.0, 0.0, 0.0, //
+            0.0, 0.0, 1.0, 0.0, //
+            0.0, 0.0, 0.0, 1.0,
+        ]);
+        tilted.transform(&shear);
+        let [a, b, c, ..] = [0, 1, 2].map(|i| tilted.vertices[i].position);
+        let face = (b - a).cross(c - a).normalize();
+        for v in &tilted.vertices {
+            assert!(v.normal.is_normalized());
+            assert!(v.normal.cross(face).length() < 1e-5, "{:?}", v.normal);
+        }
+    }
+
+    #[test]
+    fn mirroring_transform_flips_winding() {
+        let mut mesh = quad();
+        mesh.transform(&Mat4::from_scale(Vec3::new(-1.0, 1.0, 1.0)));
+        assert_eq!(mesh.indices, [0, 2, 1, 0, 3, 2]);
+        assert_eq!(mesh.vertices[1].position, Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(mesh.vertices[0].normal, Vec3::Z);
+    }
+
+    #[test]
+    fn update_ranges_reject_overflow() {
+        assert_eq!(range_end("indices", 2, 4, 6), Ok(6));
+        assert!(range_end("indices", 3, 4, 6).is_err());
+        assert!(range_end("indices", u32::MAX, 1, u32::MAX).is_err());
+        assert!(range_end("indices", 1, u32::MAX as usize, u32::MAX).is_err());
+        assert!(range_end("indices", 0, usize::MAX, u32::MAX).is_err());
+    }
+}
@@ -0,0 +1,506 @@
+//! Cooked meshes: vertex and index buffers stored exactly as they're
+//! uploaded, so loading one is a header parse and two buffer uploads. A
+//! 64-byte header, then the attributes, submeshes, vertices and indices:
+//!
+//! ```text
+//! header     "AMSH", version u16, index width u8 (2 or 4), attribute count u8,
+//!            stride u32, vertex count u32, index count u32, submesh count u32,
+//!            aabb min and max 6 x f32, sphere center and radius 4 x f32
+//! attribute  semantic u8, format u8, normalized u8, 0u8, offset u32
+//! submesh    first index u32, index count u32
+//! ```
+//!
+//! Little-endian throughout, vertices included, so they're written and read
+//! as raw bytes. Indices stay 16-bit on the GPU when they are in the file.
+use crate::prelude::*;
+use crate::utils::ByteReader;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"AMSH";
+const VERSION: u16 = 1;
+
+/// `VertexFormat`s by their number in the file.
+const FORMATS: [VertexFormat; 9] = [
+    VertexFormat::Float32,
+    VertexFormat::Float32x2,
+    VertexFormat::Float32x3,
+    VertexFormat::Float32x4,
+    VertexFormat::Uint8x4,
+    VertexFormat::Uint16x4,
+    VertexFormat::Uint32x4,
+    VertexFormat::Float16x2,
+    VertexFormat::Float16x4,
+];
+
+/// A parsed mesh file, borrowing its buffers from the file's bytes.
+#[derive(Clone, Debug)]
+pub struct MeshFile<'a> {
+    pub layout: VertexLayout,
+    pub vertex_count: u32,
+    pub index_count: u32,
+    pub index_format: IndexFormat,
+    pub submeshes: Vec<Submesh>,
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+    vertices: &'a [u8],
+    indices: &'a [u8],
+}
+
+impl<'a> MeshFile<'a> {
+    /// Checks that `bytes` starts like a mesh file, to tell it from e.g. OBJ.
+    pub fn is_mesh_file(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+    /// Reads the header and checks the buffers' sizes and that every index
+    /// is in range.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        let file = Self::parse_unchecked(bytes)?;
+        if let Some(index) = file.index_iter().find(|&index| index >= file.vertex_count) {
+            return Err(format!("invalid mesh file: index {index} out of range"));
+        }
+        Ok(file)
+    }
+    /// `parse` without the index check, for bytes `parse` already accepted.
+    pub(crate) fn parse_unchecked(bytes: &'a [u8]) -> Result<Self, String> {
+        let error = |message: &str| format!("invalid mesh file: {message}");
+        let mut reader = ByteReader(bytes);
+        if !Self::is_mesh_file(bytes) {
+            return Err(error("bad magic"));
+        }
+        reader.bytes(MAGIC.len())?;
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(error(&format!("unsupported version {version}")));
+        }
+        let index_format = match reader.u8()? {
+            2 => IndexFormat::U16,
+            4 => IndexFormat::U32,
+            width => return Err(error(&format!("{width}-byte indices"))),
+        };
+        let attribute_count = reader.u8()?;
+        let stride = reader.u32()?;
+        let vertex_count = reader.u32()?;
+        let index_count = reader.u32()?;
+        let submesh_count = reader.u32()?;
+        let mut vec3 = || -> Result<Vec3, String> {
+            Ok(Vec3::new(reader.f32()?, reader.f32()?, reader.f32()?))
+        };
+        let aabb = Aabb::new(vec3()?, vec3()?);
+        let center = vec3()?;
+        let sphere = BoundingSphere {
+            center,
+            radius: reader.f32()?,
+        };
+
+        let mut layout = VertexLayout {
+            attributes: vec![],
+            stride,
+        };
+        for _ in 0..attribute_count {
+            let semantic = *Semantic::ALL
+                .get(reader.u8()? as usize)
+                .ok_or_else(|| error("unknown semantic"))?;
+            let format = *FORMATS
+                .get(reader.u8()? as usize)
+                .ok_or_else(|| error("unknown vertex format"))?;
+            let normalized = reader.u8()? != 0;
+            reader.u8()?;
+            let offset = reader.u32()?;
+            if offset
+                .checked_add(format.size())
+                .is_none_or(|end| end > stride)
+            {
+                return Err(error("attribute outside the vertex"));
+            }
+            layout.attributes.push(VertexAttribute {
+                semantic,
+                format,
+                offset,
+                normalized,
+            });
+        }
+        let submeshes = (0..submesh_count)
+            .map(|_| {
+                let submesh = Submesh {
+                    first_index: reader.u32()?,
+                    index_count: reader.u32()?,
+                };
+                let end = submesh.first_index.checked_add(submesh.index_count);
+                if end.is_none_or(|end| end > index_count) {
+                    return Err(error("submesh outside the index buffer"));
+                }
+                Ok(submesh)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let vertex_size = vertex_count as u64 * stride as u64;
+        let index_size = index_count as u64 * index_format.size() as u64;
+        if (reader.0.len() as u64) < vertex_size + index_size {
+            return Err(error("truncated buffers"));
+        }
+        let vertices = reader.bytes(vertex_size as usize)?;
+        let indices = reader.bytes(index_size as usize)?;
+        Ok(Self {
+            layout,
+            vertex_count,
+            index_count,
+            index_format,
+            submeshes,
+            aabb,
+            sphere,
+            vertices,
+            indices,
+        })
+    }
+    /// The interleaved vertices, laid out as `layout` says.
+    pub fn vertices(&self) -> &'a [u8] {
+        self.vertices
+    }
+    /// The indices as stored, in `index_format`.
+    pub fn index_bytes(&self) -> &'a [u8] {
+        self.indices
+    }
+    /// The indices widened to `u32`.
+    pub fn indices(&self) -> Vec<u32> {
+        self.index_iter().collect()
+    }
+    fn index_iter(&self) -> impl Iterator<Item = u32> + 'a {
+        let width = self.index_format.size() as usize;
+        self.indices.chunks_exact(width).map(|index| match index {
+            &[a, b] => u16::from_le_bytes([a, b]) as u32,
+            _ => u32::from_le_bytes(index.try_into().unwrap()),
+        })
+    }
+    /// Converts back to `Vertex`es. Float attributes for position, normal,
+    /// UV and tangent are kept; anything else is dropped.
+    pub fn to_mesh_data(&self) -> MeshData {
+        let stride = self.layout.stride as usize;
+        let read = |semantic: Semantic, vertex: &[u8], out: &mut [f32]| {
+            let Some(attribute) = self.layout.attribute(semantic) else {
+                return;
+            };
+            let components = match attribute.format {
+                VertexFormat::Float32 => 1,
+                VertexFormat::Float32x2 => 2,
+                VertexFormat::Float32x3 => 3,
+                VertexFormat::Float32x4 => 4,
+                _ => return,
+            };
+            let start = attribute.offset as usize;
+            for (i, value) in out.iter_mut().enumerate().take(components) {
+                let bytes = &vertex[start + i * 4..start + i * 4 + 4];
+                *value = f32::from_le_bytes(bytes.try_into().unwrap());
+            }
+        };
+        let vertices = self
+            .vertices
+            .chunks_exact(stride.max(1))
+            .take(self.vertex_count as usize)
+            .map(|vertex| {
+                let (mut position, mut normal, mut uv, mut tangent) =
+                    ([0.0; 3], [0.0; 3], [0.0; 2], [0.0; 4]);
+                read(Semantic::Position, vertex, &mut position);
+                read(Semantic::Normal, vertex, &mut normal);
+                read(Semantic::TexCoord0, vertex, &mut uv);
+                read(Semantic::Tangent, vertex, &mut tangent);
+                Vertex {
+                    position: Vec3::from_array(position),
+                    normal: Vec3::from_array(normal),
+                    uv: Vec2::from_array(uv),
+                    tangent: Vec4::from_array(tangent),
+                }
+            })
+            .collect();
+        let mut data = MeshData::new(vertices, self.indices());
+        data.submeshes = self.submeshes.clone();
+        data
+    }
+
+    /// Encodes `data` with the `Vertex` layout, and 16-bit indices if they fit.
+    pub fn write(data: &MeshData) -> Result<Vec<u8>, String> {
+        Self::write_raw(
+            &Vertex::layout(),
+            vertex_bytes(&data.vertices),
+            &data.indices,
+            &data.submeshes,
+        )
+    }
+    /// Encodes interleaved `vertices` described by `layout`. Bounds come from
+    /// its `Float32x3` position attribute, if it has one. Fails on indices or
+    /// submeshes out of range, since loading trusts the indices of files
+    /// written here.
+    pub fn write_raw(
+        layout: &VertexLayout,
+        vertices: &[u8],
+        indices: &[u32],
+        submeshes: &[Submesh],
+    ) -> Result<Vec<u8>, String> {
+        let too_large = |_| "mesh too large".to_string();
+        let vertex_count =
+            u32::try_from(vertices.len() / layout.stride.max(1) as usize).map_err(too_large)?;
+        let index_count = u32::try_from(indices.len()).map_err(too_large)?;
+        if let Some(index) = indices.iter().find(|&&index| index >= vertex_count) {
+            return Err(format!(
+                "index {index} out of range for {vertex_count} vertices"
+            ));
+        }
+        for submesh in submeshes {
+            let end = submesh.first_index.checked_add(submesh.index_count);
+            if end.is_none_or(|end| end > index_count) {
+                return Err(format!("{submesh:?} outside the index buffer"));
+            }
+        }
+        let index_format = if vertex_count <= u16::MAX as u32 + 1 {
+            IndexFormat::U16
+        } else {
+            IndexFormat::U32
+        };
+        let positions = layout.positions(vertices);
+        let aabb = Aabb::from_points(positions.clone());
+        let sphere = BoundingSphere::from_points(positions);
+
+        let mut out = Vec::with_capacity(64 + vertices.len() + indices.len() * 4);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.push(index_format.size() as u8);
+        out.push(layout.attributes.len() as u8);
+        for value in [
+            layout.stride,
+            vertex_count,
+            index_count,
+            submeshes.len() as u32,
+        ] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        let bounds = [aabb.min, aabb.max, sphere.center];
+        for value in bounds
+            .iter()
+            .flat_map(|v| v.to_array())
+            .chain([sphere.radius])
+        {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        for attribute in &layout.attributes {
+            let format = FORMATS.iter().position(|&f| f == attribute.format);
+            out.extend_from_slice(&[
+                attribute.semantic.location() as u8,
+                format.unwrap() as u8,
+                attribute.normalized as u8,
+                0,
+            ]);
+            out.extend_from_slice(&attribute.offset.to_le_bytes());
+        }
+        for submesh in submeshes {
+            out.extend_from_slice(&submesh.first_index.to_le_bytes());
+            out.extend_from_slice(&submesh.index_count.to_le_bytes());
+        }
+        out.extend_from_slice(&vertices[..(vertex_count * layout.stride) as usize]);
+        for &index in indices {
+            match index_format {
+                // In range, so below the 65536 vertices `U16` is used for.
+                IndexFormat::U16 => out.extend_from_slice(&(index as u16).to_le_bytes()),
+                IndexFormat::U32 => out.extend_from_slice(&index.to_le_bytes()),
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// The bytes of a mesh file, mapped into memory when it's on disk so the
+/// vertices go straight from the page cache to the GPU.
+pub struct MappedMeshFile {
+    bytes: MeshBytes,
+}
+enum MeshBytes {
+    Mapped(memmap2::Mmap),
+    /// From somewhere that can't be mapped, like an archive.
+    Read(Vec<u8>),
+}
+impl MappedMeshFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        // Safety: cooked files aren't expected to change while the game runs;
+        // if one is truncated underneath us, reads of it fault.
+        let map =
+            unsafe { memmap2::Mmap::map(&file) }.map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(Self {
+            bytes: MeshBytes::Mapped(map),
+        })
+    }
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes: MeshBytes::Read(bytes),
+        }
+    }
+    pub fn bytes(&self) -> &[u8] {
+        match &self.bytes {
+            MeshBytes::Mapped(map) => map,
+            MeshBytes::Read(bytes) => bytes,
+        }
+    }
+    pub fn parse(&self) -> Result<MeshFile<'_>, String> {
+        MeshFile::parse(self.bytes())
+    }
+}
+
+impl GpuMesh {
+    /// Uploads a mesh file's buffers as they are, 16-bit indices included,
+    /// with its stored bounds.
+    pub fn from_file(renderer: &Graphics, file: &MeshFile) -> Self {
+        let mut mesh = Self::with_bounds(
+            renderer,
+            &file.layout,
+            file.vertices(),
+            (file.index_bytes(), file.index_format),
+            BufferUsage::Static,
+            file.aabb,
+            file.sphere,
+        );
+        if !file.submeshes.is_empty() {
+            mesh.submeshes = file.submeshes.clone();
+        }
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives;
+
+    fn assert_same(a: &MeshData, b: &MeshData) {
+        assert_eq!(a.vertices, b.vertices);
+        assert_eq!(a.indices, b.indices);
+        assert_eq!(a.submeshes, b.submeshes);
+    }
+
+    /// Three vertices with a position and an 8-bit color each.
+    fn colored_triangle() -> (VertexLayout, Vec<u8>) {
+        let layout = VertexLayout::new()
+            .with(Semantic::Position, VertexFormat::Float32x3)
+            .with_normalized(Semantic::Color, VertexFormat::Uint8x4, true);
+        let mut vertices = vec![];
+        for (i, position) in [Vec3::ZERO, Vec3::X, Vec3::Y].into_iter().enumerate() {
+            for value in position.to_array() {
+                vertices.extend_from_slice(&value.to_le_bytes());
+            }
+            vertices.extend_from_slice(&[255, i as u8, 0, 255]);
+        }
+        (layout, vertices)
+    }
+
+    #[test]
+    fn round_trips_mesh_data() {
+        let mesh = primitives::cube(1.0, 2);
+        let bytes = MeshFile::write(&mesh).unwrap();
+        let file = MeshFile::parse(&bytes).unwrap();
+        assert_same(&file.to_mesh_data(), &mesh);
+        assert_eq!(file.aabb, mesh.aabb());
+        assert_eq!(file.sphere, mesh.bounding_sphere());
+    }
+
+    #[test]
+    fn round_trips_submeshes() {
+        let mut mesh = primitives::cube(1.0, 2);
+        mesh.append(&primitives::icosphere(1.0, 2));
+        let bytes = MeshFile::write(&mesh).unwrap();
+        let file = MeshFile::parse(&bytes).unwrap();
+        assert_eq!(file.submeshes, mesh.submeshes);
+        assert_same(&file.to_mesh_data(), &mesh);
+    }
+
+    #[test]
+    fn index_format_follows_vertex_count() {
+        let small = primitives::cube(1.0, 1);
+        let bytes = MeshFile::write(&small).unwrap();
+        let file = MeshFile::parse(&bytes).unwrap();
+        assert_eq!(file.index_format, IndexFormat::U16);
+        assert_eq!(file.index_bytes().len(), small.indices.len() * 2);
+        assert_same(&file.to_mesh_data(), &small);
+
+        let large = primitives::grid(Vec2::ONE, UVec2::splat(300));
+        assert!(large.vertices.len() > 65536);
+        let bytes = MeshFile::write(&large).unwrap();
+        let file = MeshFile::parse(&bytes).unwrap();
+        assert_eq!(file.index_format, IndexFormat::U32);
+        assert_eq!(file.index_bytes().len(), large.indices.len() * 4);
+        assert_same(&file.to_mesh_data(), &large);
+    }
+
+    #[test]
+    fn write_raw_keeps_custom_layouts() {
+        let (layout, vertices) = colored_triangle();
+        let bytes = MeshFile::write_raw(&layout, &vertices, &[0, 1, 2], &[]).unwrap();
+        let file = MeshFile::parse(&bytes).unwrap();
+        assert_eq!(file.layout, layout);
+        assert_eq!(file.vertex_count, 3);
+        assert_eq!(file.vertices(), vertices);
+        assert_eq!(file.indices(), [0, 1, 2]);
+        assert_eq!(file.aabb, Aabb::new(Vec3::ZERO, Vec3::new(1.0, 1.0, 0.0)));
+        let data = file.to_mesh_data();
+        assert_eq!(data.vertices[1].position, Vec3::X);
+        assert_eq!(data.vertices[1].normal, Vec3::ZERO);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let bytes = MeshFile::write(&primitives::cube(1.0, 1)).unwrap();
+        assert!(MeshFile::parse(b"v 0 0 0").is_err());
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 9;
+        assert!(MeshFile::parse(&wrong_version).is_err());
+        for length in [0, 10, 64, bytes.len() - 1] {
+            assert!(MeshFile::parse(&bytes[..length]).is_err(), "{length} bytes");
+        }
+    }
+
+    #[test]
+    fn rejects_submeshes_outside_the_indices() {
+        let (layout, vertices) = colored_triangle();
+        let submesh = Submesh {
+            first_index: 3,
+            index_count: 3,
+        };
+        assert!(MeshFile::write_raw(&layout, &vertices, &[0, 1, 2], &[submesh]).is_err());
+        let submesh = Submesh {
+            first_index: 0,
+            index_count: 3,
+        };
+        let mut bytes = MeshFile::write_raw(&layout, &vertices, &[0, 1, 2], &[submesh]).unwrap();
+        // The submesh's first index, after the header and two attributes.
+        bytes[80..84].copy_from_slice(&3u32.to_le_bytes());
+        assert!(MeshFile::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_attributes_outside_the_vertex() {
+        let (layout, vertices) = colored_triangle();
+        let mut bytes = MeshFile::write_raw(&layout, &vertices, &[0, 1, 2], &[]).unwrap();
+        // The stride, right after the magic, version and two counts.
+        bytes[8..12].copy_from_slice(&12u32.to_le_bytes());
+        assert!(MeshFile::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_indices_out_of_range() {
+        let (layout, vertices) = colored_triangle();
+        let mut bytes = MeshFile::write_raw(&layout, &vertices, &[0, 1, 2], &[]).unwrap();
+        // The last 16-bit index.
+        let end = bytes.len();
+        bytes[end - 2..].copy_from_slice(&3u16.to_le_bytes());
+        assert!(MeshFile::parse(&bytes).is_err());
+        assert!(MeshFile::parse_unchecked(&bytes).is_ok());
+    }
+
+    #[test]
+    fn write_rejects_indices_out_of_range() {
+        let (layout, vertices) = colored_triangle();
+        assert!(MeshFile::write_raw(&layout, &vertices, &[0, 1, 3], &[]).is_err());
+        // Would have wrapped to 0 as a 16-bit index.
+        assert!(MeshFile::write_raw(&layout, &vertices, &[0, 1, 65536], &[]).is_err());
+        let mut mesh = primitives::cube(1.0, 1);
+        mesh.indices.push(mesh.vertices.len() as u32);
+        assert!(MeshFile::write(&mesh).is_err());
+    }
+}
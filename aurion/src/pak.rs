@@ -0,0 +1,470 @@
+//! `.pak` archives: every file of an assets directory in one file, for
+//! shipping. A 24-byte header, the file contents, then an index at the end:
+//!
+//! ```text
+//! header  "APAK", version u32, index offset u64, entry count u32, 0u32
+//! entry   path length u16, path (UTF-8), offset u64, stored size u64,
+//!         size u64, FNV-1a hash of the contents u64, compression u8
+//! ```
+//!
+//! All little-endian. Files with the same contents are stored once.
+//! LZ4 expands data at most 255 times, which bounds `size` for a corrupt
+//! archive.
+use crate::assets::fnv1a;
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 4] = b"APAK";
+const VERSION: u32 = 1;
+const HEADER_SIZE: u64 = 24;
+/// The most LZ4 can expand its input by.
+const MAX_LZ4_RATIO: u64 = 255;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PakCompression {
+    None,
+    Lz4,
+}
+
+#[derive(Clone, Debug)]
+pub struct PakEntry {
+    pub path: String,
+    offset: u64,
+    stored_size: u64,
+    /// Size of the contents once decompressed.
+    pub size: u64,
+    pub hash: u64,
+    pub compression: PakCompression,
+}
+impl PakEntry {
+    pub fn stored_size(&self) -> u64 {
+        self.stored_size
+    }
+}
+
+pub struct PakWriter {
+    file: BufWriter<File>,
+    entries: Vec<PakEntry>,
+    offset: u64,
+}
+impl PakWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        // Readable too, so `add` can compare against earlier entries.
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            entries: vec![],
+            offset: 0,
+        };
+        // Filled in by `finish`.
+        writer.write(&[0; HEADER_SIZE as usize])?;
+        Ok(writer)
+    }
+    /// Adds a file, LZ4-compressed if asked to and that makes it smaller.
+    pub fn add(
+        &mut self,
+        path: &str,
+        contents: &[u8],
+        compress: bool,
+    ) -> Result<&PakEntry, String> {
+        if path.len() > u16::MAX as usize {
+            return Err(format!("{path}: path too long"));
+        }
+        if self.entries.iter().any(|entry| entry.path == path) {
+            return Err(format!("{path}: added twice"));
+        }
+        let hash = fnv1a(contents);
+        let size = contents.len() as u64;
+        // The hash only picks candidates; the bytes have to match too.
+        let mut duplicate = None;
+        for i in 0..self.entries.len() {
+            let entry = &self.entries[i];
+            if entry.hash == hash && entry.size == size && self.read_back(i)? == contents {
+                duplicate = Some(i);
+                break;
+            }
+        }
+        let entry = match duplicate {
+            Some(duplicate) => PakEntry {
+                path: path.to_string(),
+                ..self.entries[duplicate].clone()
+            },
+            None => {
+                let compressed = compress
+                    .then(|| lz4_flex::block::compress(contents))
+                    .filter(|compressed| compressed.len() < contents.len());
+                let (stored, compression) = match &compressed {
+                    Some(compressed) => (compressed.as_slice(), PakCompression::Lz4),
+                    None => (contents, PakCompression::None),
+                };
+                let entry = PakEntry {
+                    path: path.to_string(),
+                    offset: self.offset,
+                    stored_size: stored.len() as u64,
+                    size,
+                    hash,
+                    compression,
+                };
+                self.write(stored)?;
+                entry
+            }
+        };
+        self.entries.push(entry);
+        Ok(self.entries.last().unwrap())
+    }
+    /// Writes the index and header.
+    pub fn finish(mut self) -> Result<Vec<PakEntry>, String> {
+        let index_offset = self.offset;
+        let mut index = vec![];
+        for entry in &self.entries {
+            index.extend_from_slice(&(entry.path.len() as u16).to_le_bytes());
+            index.extend_from_slice(entry.path.as_bytes());
+            for value in [entry.offset, entry.stored_size, entry.size, entry.hash] {
+                index.extend_from_slice(&value.to_le_bytes());
+            }
+            index.push(match entry.compression {
+                PakCompression::None => 0,
+                PakCompression::Lz4 => 1,
+            });
+        }
+        self.write(&index)?;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&index_offset.to_le_bytes());
+        header.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        self.file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.file.write_all(&header))
+            .and_then(|_| self.file.flush())
+            .map_err(|e| e.to_string())?;
+        Ok(self.entries)
+    }
+    /// The contents of an entry already written.
+    fn read_back(&mut self, entry: usize) -> Result<Vec<u8>, String> {
+        let entry = &self.entries[entry];
+        let mut stored = vec![0; entry.stored_size as usize];
+        // Seeking the `BufWriter` flushes it first.
+        self.file
+            .seek(SeekFrom::Start(entry.offset))
+            .and_then(|_| self.file.get_mut().read_exact(&mut stored))
+            .and_then(|_| self.file.seek(SeekFrom::End(0)))
+            .map_err(|e| e.to_string())?;
+        decompress(entry, stored)
+    }
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.file.write_all(bytes).map_err(|e| e.to_string())?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// A `.pak` archive mounted in a `Vfs`. Contents are checked against their
+/// hash on every read.
+pub struct PakMount {
+    file: Mutex<File>,
+    entries: HashMap<String, PakEntry>,
+}
+impl PakMount {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let error = |e: String| format!("{}: {e}", path.display());
+        let mut file = File::open(path).map_err(|e| error(e.to_string()))?;
+        let length = file.metadata().map_err(|e| error(e.to_string()))?.len();
+        let mut header = [0; HEADER_SIZE as usize];
+        file.read_exact(&mut header)
+            .map_err(|_| error("not a pak file".to_string()))?;
+        let mut header = ByteReader(&header);
+        if header.bytes(4)? != MAGIC {
+            return Err(error("not a pak file".to_string()));
+        }
+        let version = header.u32()?;
+        if version != VERSION {
+            return Err(error(format!("unsupported pak version {version}")));
+        }
+        let index_offset = header.u64()?;
+        let count = header.u32()?;
+        if index_offset < HEADER_SIZE || index_offset > length {
+            return Err(error("corrupt index".to_string()));
+        }
+        let mut index = vec![];
+        file.seek(SeekFrom::Start(index_offset))
+            .and_then(|_| file.read_to_end(&mut index))
+            .map_err(|e| error(e.to_string()))?;
+        let mut index = ByteReader(&index);
+        // Not sized by `count`, which a corrupt archive could make huge.
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let path_len = index.u16()? as usize;
+            let path = String::from_utf8(index.bytes(path_len)?.to_vec())
+                .map_err(|_| error("corrupt index".to_string()))?;
+            let entry = PakEntry {
+                path: path.clone(),
+                offset: index.u64()?,
+                stored_size: index.u64()?,
+                size: index.u64()?,
+                hash: index.u64()?,
+                compression: match index.u8()? {
+                    0 => PakCompression::None,
+                    1 => PakCompression::Lz4,
+                    other => return Err(error(format!("{path}: unknown compression {other}"))),
+                },
+            };
+            let end = entry.offset.checked_add(entry.stored_size);
+            if !matches!(end, Some(end) if end <= index_offset) {
+                return Err(error(format!("{path}: outside the archive")));
+            }
+            let max_size = match entry.compression {
+                PakCompression::None => entry.stored_size,
+                PakCompression::Lz4 => entry.stored_size.saturating_mul(MAX_LZ4_RATIO),
+            };
+            if entry.size > max_size
+                || (entry.compression == PakCompression::None && entry.size != max_size)
+            {
+                return Err(error(format!("{path}: corrupt size")));
+            }
+            entries.insert(path, entry);
+        }
+        Ok(Self {
+            file: Mutex::new(file),
+            entries,
+        })
+    }
+    pub fn entries(&self) -> impl Iterator<Item = &PakEntry> {
+        self.entries.values()
+    }
+}
+impl Mount for PakMount {
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        let Some(entry) = self.entries.get(path) else {
+            return Ok(None);
+        };
+        let mut stored = vec![0; entry.stored_size as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(entry.offset))
+                .and_then(|_| file.read_exact(&mut stored))
+                .map_err(|e| format!("{path}: {e}"))?;
+        }
+        let contents = decompress(entry, stored).map_err(|e| format!("{path}: {e}"))?;
+        if fnv1a(&contents) != entry.hash {
+            return Err(format!("{path}: corrupt, hash mismatch"));
+        }
+        Ok(Some(contents))
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+}
+
+/// An entry's contents from its stored bytes, checked for size.
+fn decompress(entry: &PakEntry, stored: Vec<u8>) -> Result<Vec<u8>, String> {
+    let contents = match entry.compression {
+        PakCompression::None => stored,
+        PakCompression::Lz4 => {
+            lz4_flex::block::decompress(&stored, entry.size as usize).map_err(|e| e.to_string())?
+        }
+    };
+    if contents.len() as u64 != entry.size {
+        return Err("corrupt, wrong size".to_string());
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A file path no other test or process uses.
+    fn temp_pak(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("aurion-{}-{name}.pak", std::process::id()))
+    }
+
+    fn write_pak(name: &str, files: &[(&str, &[u8], bool)]) -> (PathBuf, Vec<PakEntry>) {
+        let path = temp_pak(name);
+        let mut writer = PakWriter::create(&path).unwrap();
+        for (file, contents, compress) in files {
+            writer.add(file, contents, *compress).unwrap();
+        }
+        (path.clone(), writer.finish().unwrap())
+    }
+
+    fn read(mount: &PakMount, path: &str) -> Vec<u8> {
+        mount.read(path).unwrap().unwrap()
+    }
+
+    /// Where the index entry for `path` starts its offset field.
+    fn entry_fields(bytes: &[u8], path: &str) -> usize {
+        let index = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize;
+        let mut name = (path.len() as u16).to_le_bytes().to_vec();
+        name.extend_from_slice(path.as_bytes());
+        index
+            + bytes[index..]
+                .windows(name.len())
+                .position(|w| w == name)
+                .unwrap()
+            + name.len()
+    }
+
+    fn open_patched(name: &str, patch: impl FnOnce(&mut Vec<u8>)) -> Result<PakMount, String> {
+        let text = "compressible ".repeat(100);
+        let (path, _) = write_pak(
+            name,
+            &[
+                ("a.txt", text.as_bytes(), true),
+                ("b.bin", &[1, 2, 3], false),
+            ],
+        );
+        let mut bytes = std::fs::read(&path).unwrap();
+        patch(&mut bytes);
+        std::fs::write(&path, &bytes).unwrap();
+        let mount = PakMount::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        mount
+    }
+
+    #[test]
+    fn round_trips_through_a_mount() {
+        let text = "compressible ".repeat(100);
+        let (path, entries) = write_pak(
+            "round-trip",
+            &[
+                ("a.txt", text.as_bytes(), true),
+                ("dir/b.bin", &[1, 2, 3], true),
+                ("empty", &[], false),
+            ],
+        );
+        assert_eq!(entries[0].compression, PakCompression::Lz4);
+        assert!(entries[0].stored_size() < text.len() as u64);
+        // Compressing three bytes only makes them bigger.
+        assert_eq!(entries[1].compression, PakCompression::None);
+        let mount = PakMount::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mount.entries().count(), 3);
+        assert_eq!(read(&mount, "a.txt"), text.as_bytes());
+        assert_eq!(read(&mount, "dir/b.bin"), [1, 2, 3]);
+        assert_eq!(read(&mount, "empty"), []);
+        assert!(mount.exists("dir/b.bin"));
+        assert!(!mount.exists("b.bin"));
+        assert_eq!(mount.read("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn identical_files_are_stored_once() {
+        let text = "compressible ".repeat(100);
+        let (path, entries) = write_pak(
+            "identical",
+            &[
+                ("a.txt", text.as_bytes(), true),
+                ("b.txt", text.as_bytes(), true),
+            ],
+        );
+        assert_eq!(entries[0].offset, entries[1].offset);
+        let bytes = std::fs::read(&path).unwrap();
+        let mount = PakMount::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read(&mount, "b.txt"), text.as_bytes());
+        // The index starts right after the one copy.
+        let index_offset = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        assert_eq!(index_offset, HEADER_SIZE + entries[0].stored_size());
+    }
+
+    #[test]
+    fn matching_hashes_with_different_bytes_are_stored_apart() {
+        let path = temp_pak("collision");
+        let mut writer = PakWriter::create(&path).unwrap();
+        writer.add("a", b"first", false).unwrap();
+        // Stand in for an FNV-1a collision of the same size.
+        writer.entries[0].hash = fnv1a(b"other");
+        let entry = writer.add("b", b"other", false).unwrap();
+        assert_eq!(entry.offset, HEADER_SIZE + 5);
+        writer.entries[0].hash = fnv1a(b"first");
+        writer.finish().unwrap();
+        let mount = PakMount::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read(&mount, "a"), b"first");
+        assert_eq!(read(&mount, "b"), b"other");
+    }
+
+    #[test]
+    fn adding_a_path_twice_fails() {
+        let path = temp_pak("twice");
+        let mut writer = PakWriter::create(&path).unwrap();
+        writer.add("a", b"1", false).unwrap();
+        assert!(writer.add("a", b"2", false).is_err());
+        drop(writer);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_indices() {
+        assert!(open_patched("truncated", |bytes| {
+            bytes.truncate(bytes.len() - 1);
+        })
+        .is_err());
+        assert!(open_patched("header", |bytes| bytes.truncate(10)).is_err());
+        // More entries than the index holds, without reserving room for them.
+        assert!(open_patched("count", |bytes| {
+            bytes[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        })
+        .is_err());
+        assert!(open_patched("index-offset", |bytes| {
+            bytes[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn rejects_entries_outside_the_archive() {
+        let error = open_patched("stored-size", |bytes| {
+            let at = entry_fields(bytes, "b.bin") + 8;
+            bytes[at..at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        })
+        .err()
+        .unwrap();
+        assert!(error.contains("outside the archive"), "{error}");
+        let error = open_patched("offset", |bytes| {
+            let at = entry_fields(bytes, "b.bin");
+            bytes[at..at + 8].copy_from_slice(&(u64::MAX - 1).to_le_bytes());
+        })
+        .err()
+        .unwrap();
+        assert!(error.contains("outside the archive"), "{error}");
+    }
+
+    #[test]
+    fn rejects_impossible_sizes() {
+        for (name, file) in [("size-lz4", "a.txt"), ("size-raw", "b.bin")] {
+            let error = open_patched(name, |bytes| {
+                let at = entry_fields(bytes, file) + 16;
+                bytes[at..at + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+            })
+            .err()
+            .unwrap();
+            assert!(error.contains("corrupt size"), "{error}");
+        }
+    }
+
+    #[test]
+    fn reads_check_the_hash() {
+        let mount = open_patched("hash", |bytes| {
+            let at = bytes.windows(3).position(|w| w == [1, 2, 3]).unwrap();
+            bytes[at] = 9;
+        })
+        .unwrap();
+        assert!(mount.read("b.bin").is_err());
+    }
+}
@@ -0,0 +1,435 @@
+//! Procedural meshes. Everything is built on the CPU as `MeshData`, with
+//! counter-clockwise front faces, UVs and tangents, ready for `GpuMesh::new`.
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// A `size` by `size` square in the XZ plane facing +Y.
+pub fn plane(size: f32, subdivisions: u32) -> MeshData {
+    grid(Vec2::splat(size), UVec2::splat(subdivisions.max(1)))
+}
+
+/// A rectangle in the XZ plane facing +Y, split into `cells.x` by `cells.y` quads.
+pub fn grid(size: Vec2, cells: UVec2) -> MeshData {
+    let mut mesh = MeshData::default();
+    face(
+        &mut mesh,
+        Vec3::ZERO,
+        Vec3::X * size.x,
+        Vec3::NEG_Z * size.y,
+        cells.max(UVec2::ONE),
+    );
+    with_tangents(mesh)
+}
+
+/// A cube centered on the origin with separate vertices, normals and a full
+/// UV square per face, each face split into `subdivisions` squared quads.
+pub fn cube(size: f32, subdivisions: u32) -> MeshData {
+    let mut mesh = MeshData::default();
+    let cells = UVec2::splat(subdivisions.max(1));
+    // (normal, u axis, v axis) with u × v = normal.
+    let faces = [
+        (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+        (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+    ];
+    for (normal, u, v) in faces {
+        face(&mut mesh, normal * size * 0.5, u * size, v * size, cells);
+    }
+    with_tangents(mesh)
+}
+
+/// A sphere of `rings` latitude bands and `segments` longitude slices.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+    let rings = rings.max(2);
+    let latitudes = (0..=rings).map(|i| (PI * i as f32 / rings as f32, 0.0));
+    lathe_sphere(radius, segments.max(3), latitudes.collect(), 2.0 * radius)
+}
+
+/// A sphere made by splitting an icosahedron `subdivisions` times, which
+/// spreads triangles more evenly than `uv_sphere`. UVs are spherical, so
+/// triangles crossing the seam stretch.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    let mut positions: Vec<Vec3> = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .map(|(x, y, z)| Vec3::new(x, y, z).normalize())
+    .to_vec();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| -> u32 {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let p = (positions[a as usize] + positions[b as usize]).normalize();
+                positions.push(p);
+                positions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+    let vertices = positions
+        .iter()
+        .map(|&normal| Vertex {
+            position: normal * radius,
+            normal,
+            uv: Vec2::new(
+                0.5 + normal.x.atan2(normal.z) / TAU,
+                0.5 + normal.y.clamp(-1.0, 1.0).asin() / PI,
+            ),
+            ..Default::default()
+        })
+        .collect();
+    let indices = triangles.into_iter().flatten().collect();
+    with_tangents(MeshData::new(vertices, indices))
+}
+
+/// A closed cylinder along Y, centered on the origin.
+pub fn cylinder(radius: f32, height: f32, segments: u32, height_segments: u32) -> MeshData {
+    let (segments, rows) = (segments.max(3), height_segments.max(1));
+    let mut mesh = MeshData::default();
+    lathe_side(&mut mesh, radius, radius, height, segments, rows);
+    cap(&mut mesh, radius, height * 0.5, Vec3::Y, segments);
+    cap(&mut mesh, radius, -height * 0.5, Vec3::NEG_Y, segments);
+    with_tangents(mesh)
+}
+
+/// A cone along Y with its base at `-height / 2` and its tip at `height / 2`.
+pub fn cone(radius: f32, height: f32, segments: u32, height_segments: u32) -> MeshData {
+    let (segments, rows) = (segments.max(3), height_segments.max(1));
+    let mut mesh = MeshData::default();
+    lathe_side(&mut mesh, radius, 0.0, height, segments, rows);
+    cap(&mut mesh, radius, -height * 0.5, Vec3::NEG_Y, segments);
+    with_tangents(mesh)
+}
+
+/// A ring around Y; `major_radius` to the center of the tube, `minor_radius`
+/// of the tube itself.
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+) -> MeshData {
+    let (major, minor) = (major_segments.max(3), minor_segments.max(3));
+    let mut mesh = MeshData::default();
+    for i in 0..=major {
+        let theta = TAU * i as f32 / major as f32;
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let center = Vec3::new(sin_theta, 0.0, cos_theta) * major_radius;
+        for j in 0..=minor {
+            let phi = TAU * j as f32 / minor as f32;
+            let (sin_phi, cos_phi) = phi.sin_cos();
+            let normal = Vec3::new(cos_phi * sin_theta, sin_phi, cos_phi * cos_theta);
+            mesh.vertices.push(Vertex {
+                position: center + normal * minor_radius,
+                normal,
+                uv: Vec2::new(i as f32 / major as f32, j as f32 / minor as f32),
+                ..Default::default()
+            });
+        }
+    }
+    quads(&mut mesh, 0, major, minor, |_| (true, true));
+    with_tangents(mesh)
+}
+
+/// A cylinder of `height` capped by hemispheres, so the total height is
+/// `height + 2 * radius`. `rings` is per hemisphere.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> MeshData {
+    let rings = rings.max(1);
+    let half = height * 0.5;
+    let top = (0..=rings).map(|i| (FRAC_PI_2 * i as f32 / rings as f32, half));
+    let bottom = (0..=rings).map(|i| (FRAC_PI_2 * (1.0 + i as f32 / rings as f32), -half));
+    lathe_sphere(
+        radius,
+        segments.max(3),
+        top.chain(bottom).collect(),
+        height + 2.0 * radius,
+    )
+}
+
+/// Adds a grid of `cells` quads spanning `u` and `v` around `center`, facing
+/// `u × v`.
+fn face(mesh: &mut MeshData, center: Vec3, u: Vec3, v: Vec3, cells: UVec2) {
+    let normal = u.cross(v).normalize();
+    let start = mesh.vertices.len() as u32;
+    for row in 0..=cells.y {
+        for column in 0..=cells.x {
+            let uv = Vec2::new(column as f32 / cells.x as f32, row as f32 / cells.y as f32);
+            mesh.vertices.push(Vertex {
+                position: center + u * (uv.x - 0.5) + v * (uv.y - 0.5),
+                normal,
+                uv,
+                ..Default::default()
+            });
+        }
+    }
+    let stride = cells.x + 1;
+    for row in 0..cells.y {
+        for column in 0..cells.x {
+            let a = start + row * stride + column;
+            let (b, c, d) = (a + 1, a + stride + 1, a + stride);
+            mesh.indices.extend([a, b, c, a, c, d]);
+        }
+    }
+}
+
+/// Indexes a `(rows + 1) x (columns + 1)` vertex grid starting at `start`,
+/// laid out so that `next row × next column` points outward. `keep(row)`
+/// says which of the quad's two triangles to emit, to drop the degenerate
+/// ones at poles.
+fn quads(
+    mesh: &mut MeshData,
+    start: u32,
+    rows: u32,
+    columns: u32,
+    keep: impl Fn(u32) -> (bool, bool),
+) {
+    let stride = columns + 1;
+    for row in 0..rows {
+        let (first, second) = keep(row);
+        for column in 0..columns {
+            let a = start + row * stride + column;
+            let (b, c, d) = (a + stride, a + stride + 1, a + 1);
+            if first {
+                mesh.indices.extend([a, b, c]);
+            }
+            if second {
+                mesh.indices.extend([a, c, d]);
+            }
+        }
+    }
+}
+
+/// Sphere-like surface from `latitudes` of `(polar angle, y offset)`, top to
+/// bottom; `height` is the total extent used to map V.
+fn lathe_sphere(radius: f32, segments: u32, latitudes: Vec<(f32, f32)>, height: f32) -> MeshData {
+    let mut mesh = MeshData::default();
+    for &(phi, offset) in &latitudes {
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        for j in 0..=segments {
+            let u = j as f32 / segments as f32;
+            let (sin_theta, cos_theta) = (TAU * u).sin_cos();
+            let normal = Vec3::new(sin_phi * sin_theta, cos_phi, sin_phi * cos_theta);
+            let position = normal * radius + Vec3::Y * offset;
+            mesh.vertices.push(Vertex {
+                position,
+                normal,
+                uv: Vec2::new(u, 0.5 + position.y / height),
+                ..Default::default()
+            });
+        }
+    }
+    let rows = latitudes.len() as u32 - 1;
+    quads(&mut mesh, 0, rows, segments, |row| {
+        (row + 1 < rows, row > 0)
+    });
+    with_tangents(mesh)
+}
+
+/// The side of a cylinder or cone, from `bottom_radius` at `-height / 2` to
+/// `top_radius` at `height / 2`.
+fn lathe_side(
+    mesh: &mut MeshData,
+    bottom_radius: f32,
+    top_radius: f32,
+    height: f32,
+    segments: u32,
+    height_segments: u32,
+) {
+    let start = mesh.vertices.len() as u32;
+    let slope = (bottom_radius - top_radius) / height;
+    for i in (0..=height_segments).rev() {
+        let t = i as f32 / height_segments as f32;
+        let radius = bottom_radius + (top_radius - bottom_radius) * t;
+        for j in 0..=segments {
+            let u = j as f32 / segments as f32;
+            let (sin_theta, cos_theta) = (TAU * u).sin_cos();
+            let around = Vec3::new(sin_theta, 0.0, cos_theta);
+            mesh.vertices.push(Vertex {
+                position: around * radius + Vec3::Y * (height * (t - 0.5)),
+                normal: (around + Vec3::Y * slope).normalize(),
+                uv: Vec2::new(u, t),
+                ..Default::default()
+            });
+        }
+    }
+    let pointed = top_radius == 0.0;
+    quads(mesh, start, height_segments, segments, |row| {
+        (true, !(pointed && row == 0))
+    });
+}
+
+/// A disc at height `y` facing `normal`, which must be +Y or -Y.
+fn cap(mesh: &mut MeshData, radius: f32, y: f32, normal: Vec3, segments: u32) {
+    let center = mesh.vertices.len() as u32;
+    mesh.vertices.push(Vertex {
+        position: Vec3::Y * y,
+        normal,
+        uv: Vec2::splat(0.5),
+        ..Default::default()
+    });
+    for j in 0..=segments {
+        let (sin_theta, cos_theta) = (TAU * j as f32 / segments as f32).sin_cos();
+        mesh.vertices.push(Vertex {
+            position: Vec3::new(sin_theta * radius, y, cos_theta * radius),
+            normal,
+            uv: Vec2::new(0.5 + sin_theta * 0.5, 0.5 - cos_theta * 0.5 * normal.y),
+            ..Default::default()
+        });
+    }
+    for j in 0..segments {
+        let (a, b) = (center + 1 + j, center + 2 + j);
+        if normal.y > 0.0 {
+            mesh.indices.extend([center, a, b]);
+        } else {
+            mesh.indices.extend([center, b, a]);
+        }
+    }
+}
+
+fn with_tangents(mut mesh: MeshData) -> MeshData {
+    processing::generate_tangents(&mut mesh);
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks `mesh` is valid with unit normals pointing along `outward`, and
+    /// that every triangle winds counter-clockwise seen from that side.
+    fn check(mesh: &MeshData, outward: impl Fn(Vec3) -> Vec3) {
+        mesh.validate().unwrap();
+        assert!(!mesh.indices.is_empty());
+        for vertex in &mesh.vertices {
+            assert!((vertex.normal.length() - 1.0).abs() < 1e-4, "{vertex:?}");
+            assert!(
+                vertex.normal.dot(outward(vertex.position)) > 0.0,
+                "{vertex:?} points inward"
+            );
+        }
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+            let face = (b.position - a.position).cross(c.position - a.position);
+            // Triangles that collapse onto a pole or tip have no facing.
+            if face.length_squared() < 1e-12 {
+                continue;
+            }
+            let centroid = (a.position + b.position + c.position) / 3.0;
+            assert!(
+                face.dot(outward(centroid)) > 0.0,
+                "{triangle:?} winds clockwise"
+            );
+            for vertex in [a, b, c] {
+                assert!(
+                    face.dot(vertex.normal) > 0.0,
+                    "{triangle:?} against its normals"
+                );
+            }
+        }
+    }
+
+    /// Away from the origin, for shapes centered on it.
+    fn from_center(position: Vec3) -> Vec3 {
+        position
+    }
+
+    #[test]
+    fn plane() {
+        check(&super::plane(2.0, 3), |_| Vec3::Y);
+    }
+
+    #[test]
+    fn grid() {
+        check(&super::grid(Vec2::new(4.0, 2.0), UVec2::new(4, 2)), |_| {
+            Vec3::Y
+        });
+    }
+
+    #[test]
+    fn cube() {
+        check(&super::cube(1.0, 1), from_center);
+        check(&super::cube(2.0, 3), from_center);
+    }
+
+    #[test]
+    fn uv_sphere() {
+        check(&super::uv_sphere(1.0, 16, 8), from_center);
+        check(&super::uv_sphere(0.5, 3, 2), from_center);
+    }
+
+    #[test]
+    fn icosphere() {
+        check(&super::icosphere(1.0, 0), from_center);
+        check(&super::icosphere(2.0, 3), from_center);
+    }
+
+    #[test]
+    fn cylinder() {
+        check(&super::cylinder(1.0, 2.0, 12, 1), from_center);
+        check(&super::cylinder(0.5, 3.0, 3, 4), from_center);
+    }
+
+    #[test]
+    fn cone() {
+        check(&super::cone(1.0, 2.0, 12, 1), from_center);
+        check(&super::cone(0.5, 1.0, 3, 3), from_center);
+    }
+
+    #[test]
+    fn torus() {
+        let major_radius = 1.0;
+        check(&super::torus(major_radius, 0.25, 24, 12), |position| {
+            let ring = Vec3::new(position.x, 0.0, position.z).normalize() * major_radius;
+            position - ring
+        });
+    }
+
+    #[test]
+    fn capsule() {
+        check(&super::capsule(0.5, 1.0, 12, 4), from_center);
+        check(&super::capsule(1.0, 0.0, 3, 1), from_center);
+    }
+}
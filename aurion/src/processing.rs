@@ -0,0 +1,940 @@
+//! Passes over `MeshData`: normal and tangent generation, welding, vertex
+//! cache, overdraw and fetch optimization, and simplification for LODs. Each
+//! pass works inside submeshes and keeps them in order.
+use crate::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::ops::{Add, Range};
+
+/// Gives every triangle its own vertices with the face normal, for a faceted
+/// look. Tangents are regenerated.
+pub fn flat_normals(mesh: &mut MeshData) {
+    let mut vertices = Vec::with_capacity(mesh.indices.len());
+    for triangle in mesh.indices.chunks_exact(3) {
+        let corners = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize]);
+        let normal = face_normal(corners.map(|vertex| vertex.position)).normalize_or_zero();
+        vertices.extend(corners.map(|vertex| Vertex { normal, ..vertex }));
+    }
+    mesh.indices = (0..vertices.len() as u32).collect();
+    mesh.vertices = vertices;
+    generate_tangents(mesh);
+}
+
+/// Averages the area-weighted normals of the faces around each position.
+/// Faces meeting at more than `crease_angle` radians stay sharp, splitting
+/// vertices where needed, so a cube whose corners share vertices still gets
+/// flat sides; `PI` smooths everything. Tangents are regenerated.
+pub fn smooth_normals(mesh: &mut MeshData, crease_angle: f32) {
+    let faces: Vec<Vec3> = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|triangle| face_normal(triangle_positions(mesh, triangle)))
+        .collect();
+    let units: Vec<Vec3> = faces.iter().map(|face| face.normalize_or_zero()).collect();
+    let mut around: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+    for (face, triangle) in mesh.indices.chunks_exact(3).enumerate() {
+        for &index in triangle {
+            let faces = around
+                .entry(position_key(mesh.vertices[index as usize].position))
+                .or_default();
+            if faces.last() != Some(&face) {
+                faces.push(face);
+            }
+        }
+    }
+    let cos_crease = crease_angle.cos();
+    let mut assigned = vec![false; mesh.vertices.len()];
+    let mut split: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+    for corner in 0..mesh.indices.len() {
+        let face = corner / 3;
+        let index = mesh.indices[corner];
+        let vertex = mesh.vertices[index as usize];
+        let normal = around[&position_key(vertex.position)]
+            .iter()
+            .filter(|&&other| other == face || units[other].dot(units[face]) >= cos_crease)
+            .map(|&other| faces[other])
+            .sum::<Vec3>()
+            .try_normalize()
+            .unwrap_or(vertex.normal);
+        mesh.indices[corner] = *split
+            .entry((index, position_key(normal)))
+            .or_insert_with(|| {
+                if !assigned[index as usize] {
+                    assigned[index as usize] = true;
+                    mesh.vertices[index as usize].normal = normal;
+                    index
+                } else {
+                    mesh.vertices.push(Vertex { normal, ..vertex });
+                    mesh.vertices.len() as u32 - 1
+                }
+            });
+    }
+    generate_tangents(mesh);
+}
+
+/// Per-vertex tangents following MikkTSpace's rules: each triangle adds its
+/// UV-derived tangent weighted by the corner angle, the sum is
+/// orthogonalized against the normal, and vertices shared by triangles with
+/// mirrored UVs are split so each side gets its own bitangent sign in `w`.
+/// Triangles with degenerate UVs don't contribute, and vertices no triangle
+/// gives a tangent get any vector perpendicular to the normal.
+pub fn generate_tangents(mesh: &mut MeshData) {
+    let frames: Vec<Option<(Vec3, f32)>> = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+            let (e1, e2) = (b.position - a.position, c.position - a.position);
+            let (d1, d2) = (b.uv - a.uv, c.uv - a.uv);
+            let det = d1.x * d2.y - d2.x * d1.y;
+            if det.abs() < f32::EPSILON {
+                return None;
+            }
+            let tangent = (e1 * d2.y - e2 * d1.y) / det;
+            let bitangent = (e2 * d1.x - e1 * d2.x) / det;
+            let sign = if e1.cross(e2).cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            Some((tangent, sign))
+        })
+        .collect();
+
+    let mut signs: Vec<Option<f32>> = vec![None; mesh.vertices.len()];
+    let mut mirrored: HashMap<u32, u32> = HashMap::new();
+    for (corner, frame) in (0..mesh.indices.len()).zip(frames.iter().flat_map(|&f| [f; 3])) {
+        let Some((_, sign)) = frame else {
+            continue;
+        };
+        let index = mesh.indices[corner];
+        match signs[index as usize] {
+            None => signs[index as usize] = Some(sign),
+            Some(existing) if existing == sign => {}
+            Some(_) => {
+                mesh.indices[corner] = *mirrored.entry(index).or_insert_with(|| {
+                    mesh.vertices.push(mesh.vertices[index as usize]);
+                    signs.push(Some(sign));
+                    mesh.vertices.len() as u32 - 1
+                });
+            }
+        }
+    }
+
+    let mut tangents = vec![Vec3::ZERO; mesh.vertices.len()];
+    for (triangle, frame) in mesh.indices.chunks_exact(3).zip(&frames) {
+        let Some((tangent, _)) = frame else {
+            continue;
+        };
+        let positions = triangle_positions(mesh, triangle);
+        for corner in 0..3 {
+            let index = triangle[corner] as usize;
+            let normal = mesh.vertices[index].normal;
+            let projected = (*tangent - normal * normal.dot(*tangent)).normalize_or_zero();
+            let angle = (positions[(corner + 1) % 3] - positions[corner])
+                .angle_between(positions[(corner + 2) % 3] - positions[corner]);
+            if angle.is_finite() {
+                tangents[index] += projected * angle;
+            }
+        }
+    }
+    for ((vertex, tangent), sign) in mesh.vertices.iter_mut().zip(tangents).zip(signs) {
+        let normal = vertex.normal;
+        let tangent = (tangent - normal * normal.dot(tangent))
+            .try_normalize()
+            .unwrap_or_else(|| normal.any_orthonormal_vector());
+        vertex.tangent = tangent.extend(sign.unwrap_or(1.0));
+    }
+}
+
+/// Merges vertices whose attributes all match within `epsilon`, e.g. after
+/// importing unindexed triangles. Triangles that collapse and vertices no
+/// triangle uses are dropped; returns how many vertices were removed.
+pub fn weld(mesh: &mut MeshData, epsilon: f32) -> usize {
+    let epsilon = epsilon.max(f32::EPSILON);
+    let cell = |position: Vec3| (position / epsilon).floor().as_ivec3();
+    let close = |a: &Vertex, b: &Vertex| {
+        a.position.abs_diff_eq(b.position, epsilon)
+            && a.normal.abs_diff_eq(b.normal, epsilon)
+            && a.uv.abs_diff_eq(b.uv, epsilon)
+            && a.tangent.abs_diff_eq(b.tangent, epsilon)
+    };
+    let mut grid: HashMap<IVec3, Vec<u32>> = HashMap::new();
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(mesh.vertices.len());
+    let mut remap = Vec::with_capacity(mesh.vertices.len());
+    for vertex in &mesh.vertices {
+        let center = cell(vertex.position);
+        let found = neighbour_cells(center).find_map(|cell| {
+            grid.get(&cell)?
+                .iter()
+                .copied()
+                .find(|&index| close(&vertices[index as usize], vertex))
+        });
+        remap.push(found.unwrap_or_else(|| {
+            let index = vertices.len() as u32;
+            vertices.push(*vertex);
+            grid.entry(center).or_default().push(index);
+            index
+        }));
+    }
+    let before = mesh.vertices.len();
+    mesh.vertices = vertices;
+    for index in &mut mesh.indices {
+        *index = remap[*index as usize];
+    }
+    retain_triangles(mesh, |[a, b, c]| a != b && b != c && a != c);
+    optimize_vertex_fetch(mesh);
+    before - mesh.vertices.len()
+}
+
+fn neighbour_cells(center: IVec3) -> impl Iterator<Item = IVec3> {
+    (-1..=1).flat_map(move |x| {
+        (-1..=1).flat_map(move |y| (-1..=1).map(move |z| center + IVec3::new(x, y, z)))
+    })
+}
+
+/// Simulated post-transform cache size `optimize_vertex_cache` aims for.
+const CACHE_SIZE: usize = 32;
+
+/// Reorders triangles so consecutive ones reuse recently transformed
+/// vertices, using Tom Forsyth's linear-speed vertex cache optimization.
+pub fn optimize_vertex_cache(mesh: &mut MeshData) {
+    let vertex_count = mesh.vertices.len();
+    for range in submesh_ranges(mesh) {
+        let indices = &mut mesh.indices[range];
+        let ordered = cache_order(indices, vertex_count);
+        indices.copy_from_slice(&ordered);
+    }
+}
+
+fn vertex_score(cache_position: Option<usize>, remaining: u32) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache = match cache_position {
+        None => 0.0,
+        // The last triangle's vertices; reusing them right away gains little.
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+    };
+    // Favors vertices with few triangles left, to finish them off.
+    cache + 2.0 / (remaining as f32).sqrt()
+}
+
+fn cache_order(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let mut remaining = vec![0u32; vertex_count];
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for &index in indices {
+        remaining[index as usize] += 1;
+        offsets[index as usize + 1] += 1;
+    }
+    for i in 0..vertex_count {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut adjacency = vec![0usize; indices.len()];
+    let mut fill = offsets.clone();
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for &index in corners {
+            adjacency[fill[index as usize]] = triangle;
+            fill[index as usize] += 1;
+        }
+    }
+
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut scores: Vec<f32> = remaining
+        .iter()
+        .map(|&remaining| vertex_score(None, remaining))
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut ordered = Vec::with_capacity(indices.len());
+    let mut next_unemitted = 0;
+    let mut best = None;
+    for _ in 0..triangle_count {
+        let triangle = best.unwrap_or_else(|| {
+            while emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+            next_unemitted
+        });
+        emitted[triangle] = true;
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        ordered.extend_from_slice(corners);
+
+        let mut updated: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+        for &index in corners.iter().chain(&cache) {
+            if !updated.contains(&index) {
+                updated.push(index);
+            }
+        }
+        for &index in corners {
+            remaining[index as usize] -= 1;
+        }
+        for (position, &index) in updated.iter().enumerate() {
+            cache_position[index as usize] = (position < CACHE_SIZE).then_some(position);
+            scores[index as usize] =
+                vertex_score(cache_position[index as usize], remaining[index as usize]);
+        }
+        updated.truncate(CACHE_SIZE);
+        cache = updated;
+
+        best = None;
+        let mut best_score = f32::NEG_INFINITY;
+        for &index in &cache {
+            let range = offsets[index as usize]..offsets[index as usize + 1];
+            for &triangle in &adjacency[range] {
+                if emitted[triangle] {
+                    continue;
+                }
+                let score: f32 = indices[triangle * 3..triangle * 3 + 3]
+                    .iter()
+                    .map(|&index| scores[index as usize])
+                    .sum();
+                if score > best_score {
+                    best = Some(triangle);
+                    best_score = score;
+                }
+            }
+        }
+    }
+    ordered
+}
+
+/// Reorders the runs of triangles `optimize_vertex_cache` produced so ones
+/// facing away from the mesh center, which tend to hide the rest, draw
+/// first. Runs are split only where a triangle misses the cache on every
+/// vertex, so cache efficiency stays about the same. Run it after
+/// `optimize_vertex_cache`.
+pub fn optimize_overdraw(mesh: &mut MeshData) {
+    let center = mesh.aabb().center();
+    for range in submesh_ranges(mesh) {
+        let triangles: Vec<[u32; 3]> = mesh.indices[range.clone()]
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        let mut clusters: Vec<Range<usize>> = vec![];
+        let mut fifo: VecDeque<u32> = VecDeque::with_capacity(16);
+        for (i, triangle) in triangles.iter().enumerate() {
+            let mut misses = 0;
+            for &index in triangle {
+                if !fifo.contains(&index) {
+                    misses += 1;
+                    if fifo.len() == 16 {
+                        fifo.pop_front();
+                    }
+                    fifo.push_back(index);
+                }
+            }
+            if misses == 3 || clusters.is_empty() {
+                clusters.push(i..i);
+            }
+            clusters.last_mut().unwrap().end = i + 1;
+        }
+        let mut keyed: Vec<(f32, Range<usize>)> = clusters
+            .into_iter()
+            .map(|cluster| {
+                let mut normal = Vec3::ZERO;
+                let mut centroid = Vec3::ZERO;
+                let mut area = 0.0;
+                for triangle in &triangles[cluster.clone()] {
+                    let positions = triangle_positions(mesh, triangle);
+                    let face = face_normal(positions);
+                    normal += face;
+                    centroid += (positions[0] + positions[1] + positions[2]) / 3.0 * face.length();
+                    area += face.length();
+                }
+                let centroid = if area > 0.0 { centroid / area } else { center };
+                ((centroid - center).dot(normal.normalize_or_zero()), cluster)
+            })
+            .collect();
+        keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let reordered: Vec<u32> = keyed
+            .into_iter()
+            .flat_map(|(_, cluster)| {
+                triangles[cluster]
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        mesh.indices[range].copy_from_slice(&reordered);
+    }
+}
+
+/// Reorders vertices by first use, so drawing reads the vertex buffer
+/// mostly in order, and drops vertices no triangle uses. Run it last, after
+/// the passes that order triangles. Returns how many vertices were removed.
+pub fn optimize_vertex_fetch(mesh: &mut MeshData) -> usize {
+    let mut remap = vec![u32::MAX; mesh.vertices.len()];
+    let mut vertices = Vec::with_capacity(mesh.vertices.len());
+    for index in &mut mesh.indices {
+        let new = &mut remap[*index as usize];
+        if *new == u32::MAX {
+            *new = vertices.len() as u32;
+            vertices.push(mesh.vertices[*index as usize]);
+        }
+        *index = *new;
+    }
+    let removed = mesh.vertices.len() - vertices.len();
+    mesh.vertices = vertices;
+    removed
+}
+
+/// Squared distances to a set of planes, weighted by the area they came from.
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+    /// Upper triangle of the symmetric `n * n^T` sum: xx, xy, xz, yy, yz, zz.
+    a: [f32; 6],
+    b: Vec3,
+    c: f32,
+    weight: f32,
+}
+impl Quadric {
+    fn plane(normal: Vec3, point: Vec3, weight: f32) -> Self {
+        let d = -normal.dot(point);
+        let n = normal;
+        Self {
+            a: [
+                n.x * n.x,
+                n.x * n.y,
+                n.x * n.z,
+                n.y * n.y,
+                n.y * n.z,
+                n.z * n.z,
+            ]
+            .map(|value| value * weight),
+            b: n * d * weight,
+            c: d * d * weight,
+            weight,
+        }
+    }
+    /// Mean squared distance from `point` to the planes.
+    fn error(&self, point: Vec3) -> f32 {
+        let [xx, xy, xz, yy, yz, zz] = self.a;
+        let p = point;
+        let ap = Vec3::new(
+            xx * p.x + xy * p.y + xz * p.z,
+            xy * p.x + yy * p.y + yz * p.z,
+            xz * p.x + yz * p.y + zz * p.z,
+        );
+        let error = p.dot(ap) + 2.0 * self.b.dot(p) + self.c;
+        error.max(0.0) / self.weight.max(f32::EPSILON)
+    }
+}
+impl Add for Quadric {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self {
+            a: [0, 1, 2, 3, 4, 5].map(|i| self.a[i] + other.a[i]),
+            b: self.b + other.b,
+            c: self.c + other.c,
+            weight: self.weight + other.weight,
+        }
+    }
+}
+
+/// Moving vertex `from` onto `to`, costing `error`. `versions` are the
+/// vertices' versions when it was queued; a collapse touching either makes
+/// it stale.
+struct Collapse {
+    error: f32,
+    from: u32,
+    to: u32,
+    versions: (u32, u32),
+}
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Collapse {}
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Collapse {
+    /// Reversed, so `BinaryHeap` pops the cheapest first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.error.total_cmp(&self.error)
+    }
+}
+
+/// A lower detail copy of `mesh` for LODs, collapsing edges by quadric error
+/// until at most `target_triangles` remain or the next collapse would move
+/// the surface more than `max_error`. Vertices only move onto neighbours,
+/// so attributes stay valid; vertices on open borders, UV or normal seams
+/// and submesh boundaries stay put, as does anything a collapse would flip.
+pub fn simplify(mesh: &MeshData, target_triangles: usize, max_error: f32) -> MeshData {
+    let vertex_count = mesh.vertices.len();
+    let positions: Vec<Vec3> = mesh.vertices.iter().map(|vertex| vertex.position).collect();
+    let mut triangles: Vec<[u32; 3]> = vec![];
+    let mut submesh_of: Vec<usize> = vec![];
+    let ranges = submesh_ranges(mesh);
+    for (submesh, range) in ranges.iter().enumerate() {
+        for triangle in mesh.indices[range.clone()].chunks_exact(3) {
+            triangles.push([triangle[0], triangle[1], triangle[2]]);
+            submesh_of.push(submesh);
+        }
+    }
+
+    let mut locked = vec![false; vertex_count];
+    let mut seams: HashMap<[u32; 3], Vec<u32>> = HashMap::new();
+    for (index, position) in positions.iter().enumerate() {
+        seams
+            .entry(position_key(*position))
+            .or_default()
+            .push(index as u32);
+    }
+    for shared in seams.values().filter(|shared| shared.len() > 1) {
+        for &index in shared {
+            locked[index as usize] = true;
+        }
+    }
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+    let mut vertex_submesh: Vec<Option<usize>> = vec![None; vertex_count];
+    for (triangle, &submesh) in triangles.iter().zip(&submesh_of) {
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            match vertex_submesh[a as usize] {
+                None => vertex_submesh[a as usize] = Some(submesh),
+                Some(other) if other != submesh => locked[a as usize] = true,
+                Some(_) => {}
+            }
+        }
+    }
+    for (&(a, b), _) in edges.iter().filter(|(_, &count)| count != 2) {
+        locked[a as usize] = true;
+        locked[b as usize] = true;
+    }
+
+    let mut quadrics = vec![Quadric::default(); vertex_count];
+    let mut around: Vec<Vec<usize>> = vec![vec![]; vertex_count];
+    let mut original = Vec::with_capacity(triangles.len());
+    for (t, triangle) in triangles.iter().enumerate() {
+        let face = face_normal(triangle.map(|index| positions[index as usize]));
+        original.push(face.normalize_or_zero());
+        let length = face.length();
+        for &index in triangle {
+            around[index as usize].push(t);
+            if length > 0.0 {
+                let plane = Quadric::plane(face / length, positions[index as usize], length * 0.5);
+                quadrics[index as usize] = quadrics[index as usize] + plane;
+            }
+        }
+    }
+
+    let mut versions = vec![0u32; vertex_count];
+    let mut heap = BinaryHeap::new();
+    let queue = |heap: &mut BinaryHeap<Collapse>,
+                 quadrics: &[Quadric],
+                 versions: &[u32],
+                 from: u32,
+                 to: u32| {
+        if locked[from as usize] {
+            return;
+        }
+        let error = (quadrics[from as usize] + quadrics[to as usize]).error(positions[to as usize]);
+        heap.push(Collapse {
+            error,
+            from,
+            to,
+            versions: (versions[from as usize], versions[to as usize]),
+        });
+    };
+    for triangle in &triangles {
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            queue(&mut heap, &quadrics, &versions, a, b);
+            queue(&mut heap, &quadrics, &versions, b, a);
+        }
+    }
+
+    let mut alive = vec![true; triangles.len()];
+    let mut live = triangles.len();
+    let max_error = max_error * max_error;
+    while live > target_triangles {
+        let Some(collapse) = heap.pop() else {
+            break;
+        };
+        if collapse.error > max_error {
+            break;
+        }
+        let (from, to) = (collapse.from as usize, collapse.to as usize);
+        if collapse.versions != (versions[from], versions[to]) {
+            continue;
+        }
+        let connected = around[from]
+            .iter()
+            .any(|&t| alive[t] && triangles[t].contains(&collapse.to));
+        let flips = around[from].iter().any(|&t| {
+            if !alive[t] || triangles[t].contains(&collapse.to) {
+                return false;
+            }
+            let before = triangles[t].map(|index| positions[index as usize]);
+            let after = triangles[t].map(|index| {
+                positions[if index == collapse.from {
+                    to
+                } else {
+                    index as usize
+                }]
+            });
+            // Against the original too, so a run of small turns can't add
+            // up to a flip or stand a triangle on its edge.
+            let after = face_normal(after).normalize_or_zero();
+            after.dot(face_normal(before).normalize_or_zero()) < 1e-3
+                || after.dot(original[t]) < 0.25
+        });
+        if !connected || flips {
+            continue;
+        }
+
+        for t in std::mem::take(&mut around[from]) {
+            if !alive[t] {
+                continue;
+            }
+            if triangles[t].contains(&collapse.to) {
+                alive[t] = false;
+                live -= 1;
+            } else {
+                for index in &mut triangles[t] {
+                    if *index == collapse.from {
+                        *index = collapse.to;
+                    }
+                }
+                around[to].push(t);
+            }
+        }
+        around[to].retain(|&t| alive[t]);
+        quadrics[to] = quadrics[from] + quadrics[to];
+        versions[from] += 1;
+        versions[to] += 1;
+
+        let mut neighbours: Vec<u32> = around[to]
+            .iter()
+            .flat_map(|&t| triangles[t])
+            .filter(|&index| index != collapse.to)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        for neighbour in neighbours {
+            queue(&mut heap, &quadrics, &versions, neighbour, collapse.to);
+            queue(&mut heap, &quadrics, &versions, collapse.to, neighbour);
+        }
+    }
+
+    let mut simplified = MeshData::new(mesh.vertices.clone(), vec![]);
+    let mut submeshes = vec![];
+    for submesh in 0..ranges.len() {
+        let first_index = simplified.indices.len() as u32;
+        for (t, triangle) in triangles.iter().enumerate() {
+            if alive[t] && submesh_of[t] == submesh {
+                simplified.indices.extend(triangle);
+            }
+        }
+        submeshes.push(Submesh {
+            first_index,
+            index_count: simplified.indices.len() as u32 - first_index,
+        });
+    }
+    if !mesh.submeshes.is_empty() {
+        simplified.submeshes = submeshes;
+    }
+    optimize_vertex_fetch(&mut simplified);
+    simplified
+}
+
+/// Twice the area, along the front face's normal.
+fn face_normal([a, b, c]: [Vec3; 3]) -> Vec3 {
+    (b - a).cross(c - a)
+}
+
+fn triangle_positions(mesh: &MeshData, triangle: &[u32]) -> [Vec3; 3] {
+    [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].position)
+}
+
+/// Exact bits, with `-0.0` and `0.0` treated alike.
+fn position_key(position: Vec3) -> [u32; 3] {
+    position.to_array().map(|value| (value + 0.0).to_bits())
+}
+
+fn submesh_ranges(mesh: &MeshData) -> Vec<Range<usize>> {
+    mesh.submeshes()
+        .iter()
+        .map(|submesh| {
+            let first = submesh.first_index as usize;
+            first..first + submesh.index_count as usize
+        })
+        .collect()
+}
+
+/// Keeps the triangles `keep` accepts, shrinking each submesh to match.
+fn retain_triangles(mesh: &mut MeshData, mut keep: impl FnMut([u32; 3]) -> bool) {
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    let mut submeshes = vec![];
+    for range in submesh_ranges(mesh) {
+        let first_index = indices.len() as u32;
+        for triangle in mesh.indices[range].chunks_exact(3) {
+            let triangle = [triangle[0], triangle[1], triangle[2]];
+            if keep(triangle) {
+                indices.extend(triangle);
+            }
+        }
+        submeshes.push(Submesh {
+            first_index,
+            index_count: indices.len() as u32 - first_index,
+        });
+    }
+    mesh.indices = indices;
+    if !mesh.submeshes.is_empty() {
+        mesh.submeshes = submeshes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives;
+    use std::collections::HashSet;
+    use std::f32::consts::{FRAC_PI_4, PI};
+
+    fn vertex(position: Vec3, uv: Vec2) -> Vertex {
+        Vertex {
+            position,
+            normal: Vec3::Z,
+            uv,
+            ..Default::default()
+        }
+    }
+
+    /// Each submesh's triangles by corner position, rotated to start at the
+    /// smallest so winding is kept, and sorted.
+    fn triangle_sets(mesh: &MeshData) -> Vec<Vec<[[u32; 3]; 3]>> {
+        submesh_ranges(mesh)
+            .into_iter()
+            .map(|range| {
+                let mut triangles: Vec<[[u32; 3]; 3]> = mesh.indices[range]
+                    .chunks_exact(3)
+                    .map(|triangle| {
+                        let mut corners = triangle_positions(mesh, triangle).map(position_key);
+                        let first = (0..3).min_by_key(|&i| corners[i]).unwrap();
+                        corners.rotate_left(first);
+                        corners
+                    })
+                    .collect();
+                triangles.sort_unstable();
+                triangles
+            })
+            .collect()
+    }
+
+    fn positions_used(mesh: &MeshData) -> HashSet<[u32; 3]> {
+        mesh.indices
+            .iter()
+            .map(|&index| position_key(mesh.vertices[index as usize].position))
+            .collect()
+    }
+
+    #[test]
+    fn flat_normals_match_faces() {
+        let mut mesh = primitives::icosphere(1.0, 1);
+        flat_normals(&mut mesh);
+        assert_eq!(mesh.vertices.len(), mesh.indices.len());
+        for triangle in mesh.indices.chunks_exact(3) {
+            let face = face_normal(triangle_positions(&mesh, triangle)).normalize();
+            for &index in triangle {
+                assert!(mesh.vertices[index as usize].normal.abs_diff_eq(face, 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_normals_keep_creases_sharp() {
+        let mut mesh = primitives::cube(1.0, 2);
+        smooth_normals(&mut mesh, FRAC_PI_4);
+        for triangle in mesh.indices.chunks_exact(3) {
+            let face = face_normal(triangle_positions(&mesh, triangle)).normalize();
+            for &index in triangle {
+                assert!(mesh.vertices[index as usize].normal.abs_diff_eq(face, 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_normals_pi_smooths_everything() {
+        let mut mesh = primitives::cube(1.0, 1);
+        smooth_normals(&mut mesh, PI);
+        let mut normals: HashMap<[u32; 3], Vec3> = HashMap::new();
+        for &index in &mesh.indices {
+            let vertex = mesh.vertices[index as usize];
+            let normal = *normals
+                .entry(position_key(vertex.position))
+                .or_insert(vertex.normal);
+            assert!(vertex.normal.abs_diff_eq(normal, 1e-5));
+            // Every corner of the cube leans into all three of its faces.
+            assert!(vertex.normal.is_normalized());
+            assert!((vertex.normal * vertex.position).cmpgt(Vec3::ZERO).all());
+        }
+        assert_eq!(normals.len(), 8);
+    }
+
+    #[test]
+    fn generate_tangents_splits_mirrored_uvs() {
+        // Two quads side by side, the right one's U mirrored back from the
+        // shared edge.
+        let mut mesh = MeshData::new(
+            vec![
+                vertex(Vec3::new(0.0, 0.0, 0.0), Vec2::new(0.0, 0.0)),
+                vertex(Vec3::new(1.0, 0.0, 0.0), Vec2::new(1.0, 0.0)),
+                vertex(Vec3::new(2.0, 0.0, 0.0), Vec2::new(0.0, 0.0)),
+                vertex(Vec3::new(0.0, 1.0, 0.0), Vec2::new(0.0, 1.0)),
+                vertex(Vec3::new(1.0, 1.0, 0.0), Vec2::new(1.0, 1.0)),
+                vertex(Vec3::new(2.0, 1.0, 0.0), Vec2::new(0.0, 1.0)),
+            ],
+            vec![0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4],
+        );
+        generate_tangents(&mut mesh);
+        // The two vertices on the shared edge are duplicated.
+        assert_eq!(mesh.vertices.len(), 8);
+        for (i, triangle) in mesh.indices.chunks_exact(3).enumerate() {
+            let (tangent, sign) = if i < 2 {
+                (Vec3::X, 1.0)
+            } else {
+                (Vec3::NEG_X, -1.0)
+            };
+            for &index in triangle {
+                let vertex = mesh.vertices[index as usize];
+                assert_eq!(vertex.tangent.w, sign);
+                assert!(vertex.tangent.truncate().abs_diff_eq(tangent, 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn generate_tangents_sign_is_unit() {
+        let mut mesh = primitives::icosphere(1.0, 2);
+        generate_tangents(&mut mesh);
+        for vertex in &mesh.vertices {
+            assert!(vertex.tangent.w == 1.0 || vertex.tangent.w == -1.0);
+            assert!(vertex.tangent.truncate().is_normalized());
+            assert!(vertex.tangent.truncate().dot(vertex.normal).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn weld_merges_duplicates_and_drops_degenerate_triangles() {
+        let corners = [
+            vertex(Vec3::new(0.0, 0.0, 0.0), Vec2::new(0.0, 0.0)),
+            vertex(Vec3::new(1.0, 0.0, 0.0), Vec2::new(1.0, 0.0)),
+            vertex(Vec3::new(1.0, 1.0, 0.0), Vec2::new(1.0, 1.0)),
+            vertex(Vec3::new(0.0, 1.0, 0.0), Vec2::new(0.0, 1.0)),
+        ];
+        let nudged = Vertex {
+            position: corners[0].position + Vec3::splat(1e-6),
+            ..corners[0]
+        };
+        // An unindexed quad, then a triangle that collapses once welded.
+        let vertices = [0, 1, 2, 0, 2, 3]
+            .map(|i| corners[i])
+            .into_iter()
+            .chain([corners[0], nudged, corners[1]])
+            .collect::<Vec<_>>();
+        let mut mesh = MeshData::new(vertices, (0..9).collect());
+        assert_eq!(weld(&mut mesh, 1e-4), 5);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.triangle_count(), 2);
+        assert!(mesh.validate().is_ok());
+    }
+
+    #[test]
+    fn weld_keeps_vertices_that_differ() {
+        let mut mesh = primitives::cube(1.0, 1);
+        let before = mesh.clone();
+        // Cube corners share positions but not normals or UVs.
+        assert_eq!(weld(&mut mesh, 1e-4), 0);
+        assert_eq!(triangle_sets(&mesh), triangle_sets(&before));
+    }
+
+    /// An icosphere and a cube as two submeshes, plus a vertex nothing uses.
+    fn two_submeshes() -> MeshData {
+        let mut mesh = primitives::icosphere(1.0, 2);
+        mesh.append(&primitives::cube(1.0, 3));
+        mesh.vertices.push(Vertex::default());
+        mesh
+    }
+
+    #[test]
+    fn optimizations_keep_triangles_and_submeshes() {
+        let mut mesh = two_submeshes();
+        let triangles = triangle_sets(&mesh);
+        let submeshes = mesh.submeshes.clone();
+        optimize_vertex_cache(&mut mesh);
+        assert_eq!(triangle_sets(&mesh), triangles);
+        optimize_overdraw(&mut mesh);
+        assert_eq!(triangle_sets(&mesh), triangles);
+        assert_eq!(optimize_vertex_fetch(&mut mesh), 1);
+        assert_eq!(triangle_sets(&mesh), triangles);
+        assert_eq!(mesh.submeshes, submeshes);
+        assert!(mesh.validate().is_ok());
+    }
+
+    #[test]
+    fn optimize_vertex_fetch_orders_vertices_by_first_use() {
+        let mut mesh = two_submeshes();
+        optimize_vertex_cache(&mut mesh);
+        optimize_vertex_fetch(&mut mesh);
+        let mut next = 0;
+        for &index in &mesh.indices {
+            assert!(index <= next);
+            if index == next {
+                next += 1;
+            }
+        }
+        assert_eq!(next as usize, mesh.vertices.len());
+    }
+
+    #[test]
+    fn simplify_reaches_target_without_flipping() {
+        let mesh = primitives::icosphere(1.0, 3);
+        let simplified = simplify(&mesh, 200, f32::INFINITY);
+        assert!(simplified.triangle_count() <= 200);
+        assert!(simplified.validate().is_ok());
+        for triangle in simplified.indices.chunks_exact(3) {
+            let positions = triangle_positions(&simplified, triangle);
+            let center = (positions[0] + positions[1] + positions[2]) / 3.0;
+            assert!(face_normal(positions).dot(center) > 0.0);
+        }
+    }
+
+    #[test]
+    fn simplify_keeps_seams_in_place() {
+        let mesh = primitives::icosphere(1.0, 3);
+        let mut seen = HashSet::new();
+        let seams: Vec<[u32; 3]> = mesh
+            .vertices
+            .iter()
+            .map(|vertex| position_key(vertex.position))
+            .filter(|&key| !seen.insert(key))
+            .collect();
+        assert!(!seams.is_empty());
+        let used = positions_used(&simplify(&mesh, 0, f32::INFINITY));
+        assert!(seams.iter().all(|seam| used.contains(seam)));
+    }
+
+    #[test]
+    fn simplify_keeps_borders_in_place() {
+        let mesh = primitives::plane(2.0, 8);
+        let simplified = simplify(&mesh, 0, f32::INFINITY);
+        assert!(simplified.triangle_count() < mesh.triangle_count());
+        let used = positions_used(&simplified);
+        for vertex in &mesh.vertices {
+            let position = vertex.position;
+            if position.x.abs() == 1.0 || position.z.abs() == 1.0 {
+                assert!(used.contains(&position_key(position)), "{position}");
+            }
+        }
+    }
+}
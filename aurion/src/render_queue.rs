@@ -0,0 +1,560 @@
+//! Draws are queued during the frame and issued in one go by `end_frame`:
+//! opaque items grouped by program, material and mesh, then transparent items
+//! back to front. GL state is only touched when it actually changes.
+//!
+//! Runs of opaque items sharing program, material and mesh are merged into one
+//! instanced draw, with the model matrices streamed through an instance buffer
+//! bound to `in mat4 aInstanceModel`, attribute locations 8 to 11. Only
+//! programs that declare `aInstanceModel` are batched this way; the rest draw
+//! each item with its own `model`. Outside instanced draws those locations
+//! read the identity matrix, so a shader computing `model * aInstanceModel`
+//! works for both paths.
+use crate::material::UniformValue;
+use crate::prelude::*;
+use crate::stats::FrameStats;
+use crate::vertex::INSTANCE_MODEL_LOCATION;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Binding point of the `Frame` uniform block.
+const FRAME_BLOCK_BINDING: u32 = 0;
+
+#[derive(Clone)]
+pub(crate) struct DrawItem {
+    pub program: NativeProgram,
+    pub material: u32,
+    pub vao: NativeVertexArray,
+    pub first_index: u32,
+    pub index_count: i32,
+    pub index_format: IndexFormat,
+    pub transform: Mat4,
+    /// Mesh bounds in model space.
+    pub bounds: BoundingSphere,
+    pub state: RenderState,
+    /// Model matrices in `RenderQueue::instances` for explicitly instanced draws.
+    pub instances: Option<Range<usize>>,
+    /// LOD cross-fade for the `lodFade` uniform: drawn where the screen-space
+    /// dither is below it if positive, at or above its negation if negative,
+    /// everywhere if 0.
+    pub fade: f32,
+}
+impl DrawItem {
+    pub fn new(
+        program: NativeProgram,
+        material: u32,
+        mesh: &GpuMesh,
+        transform: Mat4,
+        state: RenderState,
+    ) -> Self {
+        Self {
+            program,
+            material,
+            vao: mesh.vao,
+            first_index: mesh.first_index,
+            index_count: mesh.index_count as i32,
+            index_format: mesh.index_format,
+            transform,
+            bounds: mesh.sphere,
+            state,
+            instances: None,
+            fade: 0.0,
+        }
+    }
+    pub fn from_material(mesh: &GpuMesh, material: &Material, transform: Mat4) -> Self {
+        let program = material
+            .shader()
+            .expect("materials are checked with is_ready before drawing")
+            .program();
+        Self::new(
+            program,
+            material.id(),
+            mesh,
+            transform,
+            material.render_state,
+        )
+    }
+    /// Items with equal keys draw the same indices of the same mesh.
+    fn key(&self) -> (NativeProgram, u32, NativeVertexArray, u32, i32, IndexFormat) {
+        (
+            self.program,
+            self.material,
+            self.vao,
+            self.first_index,
+            self.index_count,
+            self.index_format,
+        )
+    }
+    fn batches_with(&self, other: &DrawItem) -> bool {
+        self.instances.is_none()
+            && other.instances.is_none()
+            && self.key() == other.key()
+            && self.state == other.state
+            && self.fade == other.fade
+    }
+}
+
+/// Length of the run at the start of sorted `items` that can be drawn as one
+/// instanced draw.
+fn run_len(items: &[DrawItem]) -> usize {
+    1 + items[1..]
+        .iter()
+        .take_while(|item| item.batches_with(&items[0]))
+        .count()
+}
+
+/// Layout of `layout (std140) uniform Frame` in the shaders.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FrameUniforms {
+    projection: Mat4,
+    view: Mat4,
+    view_projection: Mat4,
+    camera_position: Vec4,
+}
+
+/// Per-draw uniforms of a program used this frame.
+struct ProgramState {
+    model: Option<NativeUniformLocation>,
+    lod_fade: Option<NativeUniformLocation>,
+    /// Whether the program reads `aInstanceModel`, so runs can be batched.
+    instanced: bool,
+    /// Last `lodFade` set, NaN until the first draw.
+    fade: f32,
+}
+
+struct StateCache {
+    frame: FrameUniforms,
+    programs: HashMap<NativeProgram, ProgramState>,
+    program: Option<NativeProgram>,
+    material: Option<u32>,
+    vao: Option<NativeVertexArray>,
+    render: Option<RenderState>,
+}
+
+/// A material's uniforms and textures as of when it was submitted.
+struct MaterialSnapshot {
+    uniforms: Vec<(String, UniformValue)>,
+    textures: Vec<(String, NativeTexture)>,
+}
+
+pub(crate) struct RenderQueue {
+    opaque: Vec<DrawItem>,
+    transparent: Vec<DrawItem>,
+    materials: HashMap<u32, MaterialSnapshot>,
+    instances: Vec<Mat4>,
+    /// Merge runs of identical opaque items into instanced draws.
+    pub instancing: bool,
+    /// Skip items whose bounds are outside the camera frustum.
+    pub culling: bool,
+    batch: Vec<Mat4>,
+    frame_ubo: NativeBuffer,
+    instance_vbo: NativeBuffer,
+}
+
+impl RenderQueue {
+    pub(crate) fn new(gl: &glow::Context) -> Self {
+        let frame_ubo = unsafe {
+            let buffer = gl
+                .create_buffer()
+                .expect("Cannot create frame uniform buffer");
+            gl.bind_buffer(glow::UNIFORM_BUFFER, Some(buffer));
+            gl.buffer_data_size(
+                glow::UNIFORM_BUFFER,
+                size_of::<FrameUniforms>() as i32,
+                glow::DYNAMIC_DRAW,
+            );
+            gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+            buffer
+        };
+        let instance_vbo = unsafe { gl.create_buffer().expect("Cannot create instance buffer") };
+        Self {
+            opaque: vec![],
+            transparent: vec![],
+            materials: HashMap::new(),
+            instances: vec![],
+            instancing: true,
+            culling: true,
+            batch: vec![],
+            frame_ubo,
+            instance_vbo,
+        }
+    }
+    pub(crate) fn push(&mut self, item: DrawItem, transparent: bool) {
+        if transparent {
+            self.transparent.push(item);
+        } else {
+            self.opaque.push(item);
+        }
+    }
+    /// Queues one draw of the item for each of `transforms`.
+    pub(crate) fn push_instanced(
+        &mut self,
+        mut item: DrawItem,
+        transforms: &[Mat4],
+        transparent: bool,
+    ) {
+        let start = self.instances.len();
+        self.instances.extend_from_slice(transforms);
+        item.instances = Some(start..self.instances.len());
+        self.push(item, transparent);
+    }
+    pub(crate) fn push_material(&mut self, material: &Material) {
+        self.materials
+            .entry(material.id())
+            .or_insert_with(|| MaterialSnapshot {
+                uniforms: material.uniforms().to_vec(),
+                textures: material
+                    .textures()
+                    .iter()
+                    .filter_map(|(name, texture)| Some((name.clone(), texture.get()?.raw())))
+                    .collect(),
+            });
+    }
+    pub(crate) fn flush(&mut self, gl: &glow::Context, camera: &Camera, stats: &mut FrameStats) {
+        if self.culling {
+            self.cull(&camera.frustum(), stats);
+        }
+        if self.opaque.is_empty() && self.transparent.is_empty() {
+            self.clear();
+            return;
+        }
+        self.opaque.sort_by_key(DrawItem::key);
+        let eye = camera.position;
+        let distance = |item: &DrawItem| item.transform.w_axis.truncate().distance_squared(eye);
+        self.transparent
+            .sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+
+        let frame = FrameUniforms {
+            projection: camera.projection(),
+            view: camera.view(),
+            view_projection: camera.view_projection(),
+            camera_position: eye.extend(1.0),
+        };
+        let mut state = StateCache {
+            frame,
+            programs: HashMap::new(),
+            program: None,
+            material: None,
+            vao: None,
+            render: None,
+        };
+        unsafe {
+            let bytes = core::slice::from_raw_parts(
+                &frame as *const FrameUniforms as *const u8,
+                size_of::<FrameUniforms>(),
+            );
+            gl.bind_buffer(glow::UNIFORM_BUFFER, Some(self.frame_ubo));
+            gl.buffer_sub_data_u8_slice(glow::UNIFORM_BUFFER, 0, bytes);
+            gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+            gl.bind_buffer_base(
+                glow::UNIFORM_BUFFER,
+                FRAME_BLOCK_BINDING,
+                Some(self.frame_ubo),
+            );
+            for (column, value) in Mat4::IDENTITY.to_cols_array_2d().iter().enumerate() {
+                gl.vertex_attrib_4_f32_slice(INSTANCE_MODEL_LOCATION + column as u32, value);
+            }
+
+            let opaque = std::mem::take(&mut self.opaque);
+            let mut start = 0;
+            while start < opaque.len() {
+                let batch = self.instancing
+                    && Self::use_program(gl, opaque[start].program, &mut state, stats).instanced;
+                let end = start + if batch { run_len(&opaque[start..]) } else { 1 };
+                self.draw_run(gl, &opaque[start..end], &mut state, stats);
+                start = end;
+            }
+            self.opaque = opaque;
+            let transparent = std::mem::take(&mut self.transparent);
+            for item in &transparent {
+                self.draw_run(gl, std::slice::from_ref(item), &mut state, stats);
+            }
+            self.transparent = transparent;
+            // Later passes expect the default state.
+            stats.state_changes += RenderState::default().apply(gl, state.render.as_ref());
+            gl.bind_vertex_array(None);
+            gl.use_program(None);
+        }
+        self.clear();
+    }
+    fn clear(&mut self) {
+        self.opaque.clear();
+        self.transparent.clear();
+        self.materials.clear();
+        self.instances.clear();
+    }
+    /// Drops items, and instances of instanced items, outside `frustum`.
+    fn cull(&mut self, frustum: &Frustum, stats: &mut FrameStats) {
+        let visible = |bounds: &BoundingSphere, transform: &Mat4| {
+            frustum.intersects_sphere(&bounds.transformed(transform))
+        };
+        let instances = std::mem::take(&mut self.instances);
+        for items in [&mut self.opaque, &mut self.transparent] {
+            items.retain_mut(|item| match item.instances.take() {
+                Some(range) => {
+                    let start = self.instances.len();
+                    self.instances.extend(
+                        instances[range.clone()]
+                            .iter()
+                            .filter(|transform| visible(&item.bounds, transform)),
+                    );
+                    let kept = start..self.instances.len();
+                    stats.culled += (range.len() - kept.len()) as u32;
+                    let any = !kept.is_empty();
+                    item.instances = Some(kept);
+                    any
+                }
+                None => {
+                    let keep = visible(&item.bounds, &item.transform);
+                    stats.culled += u32::from(!keep);
+                    keep
+                }
+            });
+        }
+    }
+    /// Draws `run`, a group of items sharing program, material and mesh, with
+    /// a single draw call.
+    unsafe fn draw_run(
+        &mut self,
+        gl: &glow::Context,
+        run: &[DrawItem],
+        state: &mut StateCache,
+        stats: &mut FrameStats,
+    ) {
+        let item = &run[0];
+        let program = Self::use_program(gl, item.program, state, stats);
+        if program.fade != item.fade {
+            gl.uniform_1_f32(program.lod_fade.as_ref(), item.fade);
+            program.fade = item.fade;
+        }
+        let model = program.model;
+        if state.material != Some(item.material) {
+            if let Some(material) = self.materials.get(&item.material) {
+                for (name, value) in &material.uniforms {
+                    Self::set_uniform(gl, item.program, name, value);
+                }
+                for (unit, (name, texture)) in material.textures.iter().enumerate() {
+                    gl.active_texture(glow::TEXTURE0 + unit as u32);
+                    gl.bind_texture(glow::TEXTURE_2D, Some(*texture));
+                    Self::set_uniform(gl, item.program, name, &UniformValue::I32(unit as i32));
+                    stats.state_changes += 1;
+                }
+                // The text and UI passes bind their atlases to unit 0.
+                gl.active_texture(glow::TEXTURE0);
+            }
+            state.material = Some(item.material);
+        }
+        if state.render != Some(item.state) {
+            stats.state_changes += item.state.apply(gl, state.render.as_ref());
+            state.render = Some(item.state);
+        }
+        if state.vao != Some(item.vao) {
+            gl.bind_vertex_array(Some(item.vao));
+            state.vao = Some(item.vao);
+            stats.state_changes += 1;
+        }
+        let transforms = match &item.instances {
+            Some(range) => &self.instances[range.clone()],
+            None if run.len() > 1 => {
+                self.batch.clear();
+                self.batch.extend(run.iter().map(|item| item.transform));
+                &self.batch
+            }
+            None => {
+                gl.uniform_matrix_4_f32_slice(
+                    model.as_ref(),
+                    false,
+                    &item.transform.to_cols_array(),
+                );
+                gl.draw_elements(
+                    glow::TRIANGLES,
+                    item.index_count,
+                    item.index_format.gl(),
+                    (item.first_index * item.index_format.size()) as i32,
+                );
+                stats.draw(item.index_count as u64 / 3);
+                return;
+            }
+        };
+        if transforms.is_empty() {
+            return;
+        }
+        gl.uniform_matrix_4_f32_slice(model.as_ref(), false, &Mat4::IDENTITY.to_cols_array());
+        let bytes = core::slice::from_raw_parts(
+            transforms.as_ptr() as *const u8,
+            std::mem::size_of_val(transforms),
+        );
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.instance_vbo));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, bytes, glow::STREAM_DRAW);
+        for column in 0..4 {
+            let location = INSTANCE_MODEL_LOCATION + column;
+            gl.enable_vertex_attrib_array(location);
+            gl.vertex_attrib_pointer_f32(
+                location,
+                4,
+                glow::FLOAT,
+                false,
+                size_of::<Mat4>() as i32,
+                (column as usize * size_of::<Vec4>()) as i32,
+            );
+            gl.vertex_attrib_divisor(location, 1);
+        }
+        gl.draw_elements_instanced(
+            glow::TRIANGLES,
+            item.index_count,
+            item.index_format.gl(),
+            (item.first_index * item.index_format.size()) as i32,
+            transforms.len() as i32,
+        );
+        // Leave the mesh's vertex array as the per-object path expects it.
+        for column in 0..4 {
+            gl.disable_vertex_attrib_array(INSTANCE_MODEL_LOCATION + column);
+        }
+        gl.bind_buffer(glow::ARRAY_BUFFER, None);
+        stats.state_changes += 1;
+        stats.draw(item.index_count as u64 / 3 * transforms.len() as u64);
+        stats.instances += transforms.len() as u32;
+    }
+    /// Binds `program` if it isn't already, and returns its per-frame state.
+    unsafe fn use_program<'a>(
+        gl: &glow::Context,
+        program: NativeProgram,
+        state: &'a mut StateCache,
+        stats: &mut FrameStats,
+    ) -> &'a mut ProgramState {
+        if state.program != Some(program) {
+            gl.use_program(Some(program));
+            state.program = Some(program);
+            state.material = None;
+            stats.program_binds += 1;
+        }
+        let frame = &state.frame;
+        state
+            .programs
+            .entry(program)
+            .or_insert_with(|| Self::bind_frame(gl, program, frame))
+    }
+    /// Points the program's `Frame` block at the shared buffer, or sets plain
+    /// `projection`/`view` uniforms for shaders without one, and looks up the
+    /// per-draw uniforms. Done once per program per frame, since a program id
+    /// can be reused after its shader is dropped.
+    unsafe fn bind_frame(
+        gl: &glow::Context,
+        program: NativeProgram,
+        frame: &FrameUniforms,
+    ) -> ProgramState {
+        match gl.get_uniform_block_index(program, "Frame") {
+            Some(index) => gl.uniform_block_binding(program, index, FRAME_BLOCK_BINDING),
+            None => {
+                for (name, value) in [("projection", &frame.projection), ("view", &frame.view)] {
+                    let location = gl.get_uniform_location(program, name);
+                    gl.uniform_matrix_4_f32_slice(location.as_ref(), false, &value.to_cols_array());
+                }
+            }
+        }
+        ProgramState {
+            model: gl.get_uniform_location(program, "model"),
+            lod_fade: gl.get_uniform_location(program, "lodFade"),
+            instanced: gl.get_attrib_location(program, "aInstanceModel").is_some(),
+            fade: f32::NAN,
+        }
+    }
+    unsafe fn set_uniform(
+        gl: &glow::Context,
+        program: NativeProgram,
+        name: &str,
+        value: &UniformValue,
+    ) {
+        let location = gl.get_uniform_location(program, name);
+        let location = location.as_ref();
+        match value {
+            UniformValue::F32(v) => gl.uniform_1_f32(location, *v),
+            UniformValue::I32(v) => gl.uniform_1_i32(location, *v),
+            UniformValue::Vec2(v) => gl.uniform_2_f32(location, v.x, v.y),
+            UniformValue::Vec3(v) => gl.uniform_3_f32(location, v.x, v.y, v.z),
+            UniformValue::Vec4(v) => gl.uniform_4_f32(location, v.x, v.y, v.z, v.w),
+            UniformValue::Mat4(v) => {
+                gl.uniform_matrix_4_f32_slice(location, false, &v.to_cols_array())
+            }
+        }
+    }
+    pub(crate) fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_buffer(self.frame_ubo);
+            gl.delete_buffer(self.instance_vbo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU32;
+
+    fn item(first_index: u32, index_count: i32, index_format: IndexFormat) -> DrawItem {
+        DrawItem {
+            program: NativeProgram(NonZeroU32::new(1).unwrap()),
+            material: 1,
+            vao: NativeVertexArray(NonZeroU32::new(1).unwrap()),
+            first_index,
+            index_count,
+            index_format,
+            transform: Mat4::IDENTITY,
+            bounds: BoundingSphere {
+                center: Vec3::ZERO,
+                radius: 1.0,
+            },
+            state: RenderState::default(),
+            instances: None,
+            fade: 0.0,
+        }
+    }
+
+    fn runs(mut items: Vec<DrawItem>) -> Vec<usize> {
+        items.sort_by_key(DrawItem::key);
+        let mut runs = vec![];
+        let mut start = 0;
+        while start < items.len() {
+            runs.push(run_len(&items[start..]));
+            start += runs.last().unwrap();
+        }
+        runs
+    }
+
+    #[test]
+    fn identical_draws_batch() {
+        let full = item(0, 36, IndexFormat::U32);
+        assert_eq!(runs(vec![full.clone(), full.clone(), full]), [3]);
+    }
+
+    #[test]
+    fn submesh_and_full_mesh_at_the_same_first_index_stay_separate() {
+        let full = item(0, 36, IndexFormat::U32);
+        let submesh = item(0, 6, IndexFormat::U32);
+        assert_eq!(
+            runs(vec![full.clone(), submesh.clone(), full, submesh]),
+            [2, 2]
+        );
+    }
+
+    #[test]
+    fn index_formats_stay_separate() {
+        let wide = item(0, 36, IndexFormat::U32);
+        let narrow = item(0, 36, IndexFormat::U16);
+        assert_eq!(runs(vec![wide, narrow]), [1, 1]);
+    }
+
+    #[test]
+    fn different_fades_and_explicit_instances_stay_separate() {
+        let full = item(0, 36, IndexFormat::U32);
+        let faded = DrawItem {
+            fade: 0.5,
+            ..full.clone()
+        };
+        let instanced = DrawItem {
+            instances: Some(0..2),
+            ..full.clone()
+        };
+        assert_eq!(runs(vec![full, faded, instanced]), [1, 1, 1]);
+    }
+}
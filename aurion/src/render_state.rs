@@ -0,0 +1,239 @@
+//! Fixed-function state a material is drawn with. The render queue applies
+//! only the parts that differ from the previous draw.
+use crate::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BlendMode {
+    #[default]
+    Opaque,
+    /// `src * a + dst * (1 - a)`.
+    Alpha,
+    /// Color already multiplied by alpha: `src + dst * (1 - a)`.
+    Premultiplied,
+    Additive,
+    Multiply,
+}
+impl BlendMode {
+    /// Source and destination factors, `None` when blending is off.
+    fn factors(self) -> Option<(u32, u32)> {
+        match self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some((glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::Premultiplied => Some((glow::ONE, glow::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::Additive => Some((glow::SRC_ALPHA, glow::ONE)),
+            BlendMode::Multiply => Some((glow::DST_COLOR, glow::ZERO)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compare {
+    Never,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+    Always,
+}
+impl Compare {
+    fn gl(self) -> u32 {
+        match self {
+            Compare::Never => glow::NEVER,
+            Compare::Less => glow::LESS,
+            Compare::LessEqual => glow::LEQUAL,
+            Compare::Equal => glow::EQUAL,
+            Compare::NotEqual => glow::NOTEQUAL,
+            Compare::GreaterEqual => glow::GEQUAL,
+            Compare::Greater => glow::GREATER,
+            Compare::Always => glow::ALWAYS,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CullMode {
+    #[default]
+    None,
+    Back,
+    Front,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    /// Wireframe.
+    Line,
+    Point,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    Increment,
+    IncrementWrap,
+    Decrement,
+    DecrementWrap,
+    Invert,
+}
+impl StencilOp {
+    fn gl(self) -> u32 {
+        match self {
+            StencilOp::Keep => glow::KEEP,
+            StencilOp::Zero => glow::ZERO,
+            StencilOp::Replace => glow::REPLACE,
+            StencilOp::Increment => glow::INCR,
+            StencilOp::IncrementWrap => glow::INCR_WRAP,
+            StencilOp::Decrement => glow::DECR,
+            StencilOp::DecrementWrap => glow::DECR_WRAP,
+            StencilOp::Invert => glow::INVERT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StencilState {
+    pub compare: Compare,
+    pub reference: i32,
+    pub read_mask: u32,
+    pub write_mask: u32,
+    pub fail: StencilOp,
+    pub depth_fail: StencilOp,
+    pub pass: StencilOp,
+}
+impl Default for StencilState {
+    /// Always passes and writes `reference`, e.g. to mark pixels for a later
+    /// `Compare::Equal` pass.
+    fn default() -> Self {
+        Self {
+            compare: Compare::Always,
+            reference: 1,
+            read_mask: 0xff,
+            write_mask: 0xff,
+            fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: StencilOp::Replace,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RenderState {
+    pub blend: BlendMode,
+    /// `None` disables the depth test.
+    pub depth_test: Option<Compare>,
+    pub depth_write: bool,
+    pub cull: CullMode,
+    pub polygon: PolygonMode,
+    /// `None` disables the stencil test.
+    pub stencil: Option<StencilState>,
+}
+impl Default for RenderState {
+    fn default() -> Self {
+        Self {
+            blend: BlendMode::Opaque,
+            depth_test: Some(Compare::Less),
+            depth_write: true,
+            cull: CullMode::None,
+            polygon: PolygonMode::Fill,
+            stencil: None,
+        }
+    }
+}
+impl RenderState {
+    /// Alpha blended, depth tested but not written, as suits most
+    /// transparent surfaces.
+    pub fn transparent() -> Self {
+        Self {
+            blend: BlendMode::Alpha,
+            depth_write: false,
+            ..Self::default()
+        }
+    }
+    pub fn wireframe() -> Self {
+        Self {
+            polygon: PolygonMode::Line,
+            ..Self::default()
+        }
+    }
+    /// Drawn in the back-to-front transparent pass.
+    pub fn is_transparent(&self) -> bool {
+        self.blend != BlendMode::Opaque
+    }
+
+    /// Sets whatever differs from `current`, or everything when the GL state
+    /// is unknown, and returns how many pieces of state changed.
+    pub(crate) fn apply(&self, gl: &glow::Context, current: Option<&RenderState>) -> u32 {
+        let mut changes = 0;
+        unsafe {
+            if current.is_none_or(|c| c.blend != self.blend) {
+                match self.blend.factors() {
+                    Some((src, dst)) => {
+                        gl.enable(glow::BLEND);
+                        gl.blend_func(src, dst);
+                    }
+                    None => gl.disable(glow::BLEND),
+                }
+                changes += 1;
+            }
+            if current.is_none_or(|c| c.depth_test != self.depth_test) {
+                match self.depth_test {
+                    Some(compare) => {
+                        gl.enable(glow::DEPTH_TEST);
+                        gl.depth_func(compare.gl());
+                    }
+                    None => gl.disable(glow::DEPTH_TEST),
+                }
+                changes += 1;
+            }
+            if current.is_none_or(|c| c.depth_write != self.depth_write) {
+                gl.depth_mask(self.depth_write);
+                changes += 1;
+            }
+            if current.is_none_or(|c| c.cull != self.cull) {
+                match self.cull {
+                    CullMode::None => gl.disable(glow::CULL_FACE),
+                    CullMode::Back | CullMode::Front => {
+                        gl.enable(glow::CULL_FACE);
+                        gl.cull_face(if self.cull == CullMode::Back {
+                            glow::BACK
+                        } else {
+                            glow::FRONT
+                        });
+                    }
+                }
+                changes += 1;
+            }
+            if current.is_none_or(|c| c.polygon != self.polygon) {
+                let mode = match self.polygon {
+                    PolygonMode::Fill => glow::FILL,
+                    PolygonMode::Line => glow::LINE,
+                    PolygonMode::Point => glow::POINT,
+                };
+                gl.polygon_mode(glow::FRONT_AND_BACK, mode);
+                changes += 1;
+            }
+            if current.is_none_or(|c| c.stencil != self.stencil) {
+                match self.stencil {
+                    Some(stencil) => {
+                        gl.enable(glow::STENCIL_TEST);
+                        gl.stencil_func(stencil.compare.gl(), stencil.reference, stencil.read_mask);
+                        gl.stencil_mask(stencil.write_mask);
+                        gl.stencil_op(
+                            stencil.fail.gl(),
+                            stencil.depth_fail.gl(),
+                            stencil.pass.gl(),
+                        );
+                    }
+                    None => gl.disable(glow::STENCIL_TEST),
+                }
+                changes += 1;
+            }
+        }
+        changes
+    }
+}
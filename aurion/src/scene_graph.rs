@@ -1,12 +1,44 @@
 use crate::prelude::*;
+use crate::stats::{self, Phase};
+use crate::trace::trace_span;
 use glam::Mat4;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::{Rc, Weak};
 type LinkRef = Rc<RefCell<GameObjectNode>>;
-pub struct GameObject(LinkRef);
+#[derive(Clone)]
+pub struct GameObject(pub(crate) LinkRef);
 impl GameObject {
     pub fn add_behavior(&mut self, behavior: MonoBehavior) {
-        self.0.borrow_mut().behavior.push(behavior);
+        self.0.borrow_mut().behavior.push(Attached {
+            behavior,
+            enabled: Cell::new(true),
+        });
+    }
+    pub fn behaviors(&self) -> Vec<MonoBehavior> {
+        self.0
+            .borrow()
+            .behavior
+            .iter()
+            .map(|attached| Rc::clone(&attached.behavior))
+            .collect()
+    }
+    pub fn is_behavior_enabled(&self, index: usize) -> bool {
+        self.0.borrow().behavior[index].enabled.get()
+    }
+    /// Disabled behaviors are skipped by `GameObjectNode::update`.
+    pub fn set_behavior_enabled(&self, index: usize, enabled: bool) {
+        self.0.borrow().behavior[index].enabled.set(enabled);
+    }
+    pub fn children(&self) -> Vec<GameObject> {
+        self.0
+            .borrow()
+            .children
+            .iter()
+            .map(|child| GameObject(Rc::clone(child)))
+            .collect()
+    }
+    pub fn ptr_eq(&self, other: &GameObject) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
     }
     pub fn set_local_transform(&self, transform: Mat4) {
         *self.0.borrow().local_transform.borrow_mut() = transform;
@@ -17,24 +49,124 @@ impl GameObject {
     pub fn get_local_transform(&self) -> Mat4 {
         *self.0.borrow().local_transform.borrow()
     }
+    /// Sets the model-space bounds of what the node draws, usually `GpuMesh::aabb`.
+    pub fn set_bounds(&self, bounds: Option<Aabb>) {
+        self.0.borrow().bounds.set(bounds);
+    }
+    /// The node's bounds moved by its world transform, as of the last update.
+    pub fn get_world_bounds(&self) -> Option<Aabb> {
+        self.0.borrow().world_bounds.get()
+    }
+    /// Sets what `GameObjectNode::draw` draws for this node, and its bounds.
+    pub fn set_mesh(&self, mesh: Option<Handle<GpuMesh>>) {
+        let node = self.0.borrow();
+        node.bounds.set(
+            mesh.as_ref()
+                .and_then(|mesh| mesh.get())
+                .map(|mesh| mesh.aabb),
+        );
+        *node.mesh.borrow_mut() = mesh;
+    }
+    pub fn mesh(&self) -> Option<Handle<GpuMesh>> {
+        self.0.borrow().mesh.borrow().clone()
+    }
+    pub fn set_material(&self, material: Option<Handle<Material>>) {
+        *self.0.borrow().material.borrow_mut() = material;
+    }
+    pub fn material(&self) -> Option<Handle<Material>> {
+        self.0.borrow().material.borrow().clone()
+    }
+    /// Attaches the meshes `Graphics::submit_lod` picks from for this node.
+    pub fn set_lod_group(&self, group: Option<LodGroup>) {
+        *self.0.borrow().lod_group.borrow_mut() = group;
+    }
+    pub fn lod_group(&self) -> Option<LodGroup> {
+        self.0.borrow().lod_group.borrow().clone()
+    }
     pub fn get_name(&self) -> String {
         self.0.borrow().name.clone()
     }
+    /// Runs `callback` once, `delay` scaled seconds from now.
+    pub fn after(&self, delay: f32, callback: impl FnOnce(&GameObject) + 'static) {
+        let mut callback = Some(callback);
+        self.schedule(delay, None, move |this| {
+            if let Some(callback) = callback.take() {
+                callback(this);
+            }
+            false
+        });
+    }
+    /// Runs `callback` every `interval` scaled seconds for as long as it returns `true`.
+    pub fn every(&self, interval: f32, callback: impl FnMut(&GameObject) -> bool + 'static) {
+        self.schedule(interval, Some(interval), callback);
+    }
+    fn schedule(
+        &self,
+        delay: f32,
+        interval: Option<f32>,
+        callback: impl FnMut(&GameObject) -> bool + 'static,
+    ) {
+        self.0.borrow().timers.borrow_mut().push(Timer {
+            remaining: delay,
+            interval,
+            callback: Box::new(callback),
+        });
+    }
 }
 type WeakLink = Weak<RefCell<GameObjectNode>>;
 pub type MonoBehavior = Rc<RefCell<dyn Behavior>>;
 pub trait Behavior {
     fn awake(&mut self, renderer: &Graphics);
-    fn update(&mut self, this: GameObject, delta_time: f32);
+    fn update(&mut self, this: GameObject, time: &Time);
+    fn name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+    /// Behaviors with tweakable fields return themselves here.
+    fn inspect(&mut self) -> Option<&mut dyn Inspect> {
+        None
+    }
+}
+struct Attached {
+    behavior: MonoBehavior,
+    enabled: Cell<bool>,
 }
 pub type Transform = RefCell<Mat4>;
 
+struct Timer {
+    remaining: f32,
+    interval: Option<f32>,
+    callback: Box<dyn FnMut(&GameObject) -> bool>,
+}
+impl Timer {
+    /// Returns whether the timer should stay scheduled.
+    fn tick(&mut self, this: &GameObject, delta_time: f32) -> bool {
+        self.remaining -= delta_time;
+        if self.remaining > 0.0 {
+            return true;
+        }
+        let keep = (self.callback)(this);
+        match self.interval {
+            Some(interval) if keep => {
+                self.remaining += interval.max(f32::EPSILON);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
 pub struct GameObjectNode {
     name: String,
-    mesh_id: Option<MeshId>,
+    mesh: RefCell<Option<Handle<GpuMesh>>>,
+    material: RefCell<Option<Handle<Material>>>,
     local_transform: Transform,
     world_transform: Transform,
-    behavior: Vec<MonoBehavior>,
+    bounds: Cell<Option<Aabb>>,
+    world_bounds: Cell<Option<Aabb>>,
+    lod_group: RefCell<Option<LodGroup>>,
+    behavior: Vec<Attached>,
+    timers: RefCell<Vec<Timer>>,
     parent: Option<WeakLink>,
     children: Vec<LinkRef>,
 }
@@ -42,11 +174,16 @@ impl GameObjectNode {
     pub fn new(name: &str) -> GameObject {
         GameObject(Rc::new(RefCell::new(Self {
             behavior: vec![],
+            timers: RefCell::new(vec![]),
             parent: None,
             world_transform: RefCell::new(Mat4::IDENTITY),
             local_transform: RefCell::new(Mat4::IDENTITY),
+            bounds: Cell::new(None),
+            world_bounds: Cell::new(None),
+            lod_group: RefCell::new(None),
             children: vec![],
-            mesh_id: None,
+            mesh: RefCell::new(None),
+            material: RefCell::new(None),
             name: name.to_string(),
         })))
     }
@@ -64,13 +201,14 @@ impl GameObjectNode {
         child.0.borrow_mut().parent = None;
     }
     pub fn init(root: &GameObject, renderer: &Graphics) {
+        let _span = trace_span!("GameObjectNode::init");
         let mut stack = vec![Rc::clone(&root.0)];
         while !stack.is_empty() {
             let node = stack.pop().unwrap();
             let node = node.borrow();
 
-            for behavour in &node.behavior {
-                behavour.borrow_mut().awake(renderer);
+            for attached in &node.behavior {
+                attached.behavior.borrow_mut().awake(renderer);
             }
             for child in &node.children {
                 stack.push(Rc::clone(child));
@@ -78,26 +216,80 @@ impl GameObjectNode {
         }
     }
 
-    pub fn update(root: &GameObject, delta_time: f32) {
+    pub fn update(root: &GameObject, time: &Time) {
+        let _span = trace_span!("GameObjectNode::update");
         let mut stack = vec![Rc::clone(&root.0)];
         while !stack.is_empty() {
             let node_rc = stack.pop().unwrap();
             let node = node_rc.borrow();
 
-            for behavior in &node.behavior {
-                behavior
-                    .borrow_mut()
-                    .update(GameObject(Rc::clone(&node_rc)), delta_time);
-            }
-            if node.parent.is_some() {
-                let parent = node.parent.as_ref().unwrap().upgrade().unwrap();
-                *node.world_transform.borrow_mut() =
-                    *parent.borrow().world_transform.borrow() * *node.local_transform.borrow();
-            }
+            stats::time(Phase::Behaviors, || {
+                for attached in node.behavior.iter().filter(|a| a.enabled.get()) {
+                    let mut behavior = attached.behavior.borrow_mut();
+                    let _span = trace_span!(
+                        "Behavior::update",
+                        behavior = behavior.name(),
+                        node = node.name.as_str()
+                    );
+                    behavior.update(GameObject(Rc::clone(&node_rc)), time);
+                }
+                // Timers may schedule more timers, so run them from a detached list.
+                let mut timers = std::mem::take(&mut *node.timers.borrow_mut());
+                if !timers.is_empty() {
+                    let this = GameObject(Rc::clone(&node_rc));
+                    timers.retain_mut(|timer| timer.tick(&this, time.delta()));
+                    let mut scheduled = node.timers.borrow_mut();
+                    timers.append(&mut scheduled);
+                    *scheduled = timers;
+                }
+            });
+            stats::time(Phase::Transforms, || {
+                if node.parent.is_some() {
+                    let parent = node.parent.as_ref().unwrap().upgrade().unwrap();
+                    *node.world_transform.borrow_mut() =
+                        *parent.borrow().world_transform.borrow() * *node.local_transform.borrow();
+                }
+                // A mesh that was still loading when set gets its bounds here.
+                if node.bounds.get().is_none() {
+                    if let Some(mesh) = node.mesh.borrow().as_ref().and_then(Handle::loaded) {
+                        node.bounds.set(Some(mesh.aabb));
+                    }
+                }
+                let world_bounds = node
+                    .bounds
+                    .get()
+                    .map(|bounds| bounds.transformed(&node.world_transform.borrow()));
+                node.world_bounds.set(world_bounds);
+            });
 
             for child in &node.children {
                 stack.push(Rc::clone(child));
             }
         }
     }
+    /// Submits the mesh, or LOD group, of every node under `root` that has a
+    /// material.
+    pub fn draw(root: &GameObject, graphics: &Graphics) {
+        let mut stack = vec![Rc::clone(&root.0)];
+        while let Some(node_rc) = stack.pop() {
+            let node = node_rc.borrow();
+            if let Some(material) = node.material.borrow().as_ref().and_then(|m| m.get()) {
+                if node.lod_group.borrow().is_some() {
+                    graphics.submit_lod(&GameObject(Rc::clone(&node_rc)), material);
+                } else if let Some(mesh) = node.mesh.borrow().as_ref().and_then(|m| m.get()) {
+                    graphics.submit(mesh, material, &node.world_transform.borrow());
+                }
+            }
+            stack.extend(node.children.iter().cloned());
+        }
+    }
+    /// Queues a debug gizmo for the world-space axes of every node under `root`.
+    pub fn draw_axes(root: &GameObject, size: f32) {
+        let mut stack = vec![Rc::clone(&root.0)];
+        while let Some(node) = stack.pop() {
+            let node = node.borrow();
+            debug::axes(&node.world_transform.borrow(), size);
+            stack.extend(node.children.iter().cloned());
+        }
+    }
 }
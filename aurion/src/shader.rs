@@ -1,22 +1,27 @@
 use crate::graphics;
 use crate::prelude::*;
+use crate::vertex::INSTANCE_MODEL_LOCATION;
 use glow::{HasContext, NativeProgram};
+use std::sync::Arc;
 
-pub struct Shader<'a> {
+pub struct Shader {
     program: NativeProgram,
-    gl: &'a glow::Context,
+    gl: Arc<glow::Context>,
 }
-impl<'a> Shader<'a> {
+impl Shader {
     pub fn new(
-        renderer: &'a graphics::Graphics,
+        renderer: &graphics::Graphics,
         vertex_source: &str,
         fragment_source: &str,
-    ) -> Self {
-        let p = Shader::create_program(&renderer.gl, &vertex_source, &fragment_source);
-        Shader {
+    ) -> Result<Self, String> {
+        let p = Shader::create_program(&renderer.gl, vertex_source, fragment_source)?;
+        Ok(Shader {
             program: p,
-            gl: &renderer.gl,
-        }
+            gl: Arc::clone(&renderer.gl),
+        })
+    }
+    pub(crate) fn program(&self) -> NativeProgram {
+        self.program
     }
     pub fn use_program(&self) {
         unsafe {
@@ -46,45 +51,73 @@ impl<'a> Shader<'a> {
             );
         }
     }
-    fn create_program(
+    pub(crate) fn create_program(
         gl: &glow::Context,
         vertex_shader_source: &str,
         fragment_shader_source: &str,
-    ) -> NativeProgram {
+    ) -> Result<NativeProgram, String> {
         let shader_sources = [
             (glow::VERTEX_SHADER, vertex_shader_source),
             (glow::FRAGMENT_SHADER, fragment_shader_source),
         ];
         unsafe {
-            let program = gl.create_program().expect("Cannot create program");
+            let program = gl.create_program()?;
             let mut shaders = Vec::with_capacity(shader_sources.len());
+            let mut error = None;
             for (shader_type, shader_source) in shader_sources.iter() {
-                let shader = gl
-                    .create_shader(*shader_type)
-                    .expect("Cannot create shader");
+                let shader = gl.create_shader(*shader_type)?;
                 gl.shader_source(shader, shader_source);
                 gl.compile_shader(shader);
-                if !gl.get_shader_compile_status(shader) {
-                    panic!("{}", gl.get_shader_info_log(shader));
-                }
                 gl.attach_shader(program, shader);
                 shaders.push(shader);
+                if !gl.get_shader_compile_status(shader) {
+                    let stage = if *shader_type == glow::VERTEX_SHADER {
+                        "vertex"
+                    } else {
+                        "fragment"
+                    };
+                    error = Some(format!(
+                        "{stage} shader failed to compile: {}",
+                        gl.get_shader_info_log(shader)
+                    ));
+                    break;
+                }
             }
 
-            gl.link_program(program);
-            if !gl.get_program_link_status(program) {
-                panic!("{}", gl.get_program_info_log(program));
+            if error.is_none() {
+                for semantic in Semantic::ALL {
+                    gl.bind_attrib_location(
+                        program,
+                        semantic.location(),
+                        semantic.attribute_name(),
+                    );
+                }
+                gl.bind_attrib_location(program, INSTANCE_MODEL_LOCATION, "aInstanceModel");
+                gl.link_program(program);
+                if !gl.get_program_link_status(program) {
+                    error = Some(format!(
+                        "program failed to link: {}",
+                        gl.get_program_info_log(program)
+                    ));
+                }
             }
 
             for shader in shaders {
                 gl.detach_shader(program, shader);
                 gl.delete_shader(shader);
             }
-            program
+            match error {
+                Some(error) => {
+                    ::log::error!("{error}");
+                    gl.delete_program(program);
+                    Err(error)
+                }
+                None => Ok(program),
+            }
         }
     }
 }
-impl<'a> Drop for Shader<'a> {
+impl Drop for Shader {
     fn drop(&mut self) {
         unsafe {
             self.gl.delete_program(self.program);
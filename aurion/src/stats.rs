@@ -0,0 +1,167 @@
+//! Per-frame counters and timings collected by `Graphics`, readable through
+//! `Graphics::stats` once a frame has ended.
+use crate::prelude::*;
+use std::cell::RefCell;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Events,
+    /// Everything `Game::update` does, including the two phases below.
+    Update,
+    Behaviors,
+    Transforms,
+    Render,
+}
+impl Phase {
+    pub const ALL: [Phase; 5] = [
+        Phase::Events,
+        Phase::Update,
+        Phase::Behaviors,
+        Phase::Transforms,
+        Phase::Render,
+    ];
+}
+
+thread_local! {
+    static CPU_TIMES: RefCell<[Duration; 5]> = const { RefCell::new([Duration::ZERO; 5]) };
+}
+
+/// Adds `duration` to the CPU time of `phase` for the frame in flight.
+pub fn record(phase: Phase, duration: Duration) {
+    CPU_TIMES.with_borrow_mut(|times| times[phase as usize] += duration);
+}
+
+pub fn time<R>(phase: Phase, f: impl FnOnce() -> R) -> R {
+    let start = Instant::now();
+    let result = f();
+    record(phase, start.elapsed());
+    result
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FrameStats {
+    pub frame: u64,
+    pub draw_calls: u32,
+    pub triangles: u64,
+    /// Vertex array, texture and fixed-function state changes.
+    pub state_changes: u32,
+    pub program_binds: u32,
+    /// Objects drawn through instanced draw calls.
+    pub instances: u32,
+    /// Objects skipped because they were outside the view frustum.
+    pub culled: u32,
+    pub cpu_times: [Duration; 5],
+    /// Measured with `GL_TIME_ELAPSED`; lags a couple of frames behind and is
+    /// `None` when timer queries are unavailable.
+    pub gpu_time: Option<Duration>,
+}
+impl FrameStats {
+    pub fn cpu_time(&self, phase: Phase) -> Duration {
+        self.cpu_times[phase as usize]
+    }
+    pub(crate) fn draw(&mut self, triangles: u64) {
+        self.draw_calls += 1;
+        self.triangles += triangles;
+    }
+    pub(crate) fn take_cpu_times(&mut self) {
+        self.cpu_times = CPU_TIMES.with_borrow_mut(std::mem::take);
+    }
+    pub fn show(&self, ctx: &egui::Context) {
+        egui::Window::new("Frame stats")
+            .default_pos([10.0, 40.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("frame_stats").show(ui, |ui| {
+                    let mut row = |name: &str, value: String| {
+                        ui.label(name);
+                        ui.label(value);
+                        ui.end_row();
+                    };
+                    row("draw calls", self.draw_calls.to_string());
+                    row("triangles", self.triangles.to_string());
+                    row("state changes", self.state_changes.to_string());
+                    row("program binds", self.program_binds.to_string());
+                    row("instances", self.instances.to_string());
+                    row("culled", self.culled.to_string());
+                    for phase in Phase::ALL {
+                        let ms = self.cpu_time(phase).as_secs_f64() * 1000.0;
+                        row(&format!("{phase:?}"), format!("{ms:.2} ms"));
+                    }
+                    let gpu = self.gpu_time.map_or("n/a".to_string(), |gpu| {
+                        format!("{:.2} ms", gpu.as_secs_f64() * 1000.0)
+                    });
+                    row("GPU", gpu);
+                });
+            });
+    }
+}
+
+const GPU_QUERIES: usize = 3;
+
+/// Rotates through a few `GL_TIME_ELAPSED` queries so results are read a
+/// couple of frames late instead of stalling on the GPU.
+pub(crate) struct GpuTimer {
+    queries: [NativeQuery; GPU_QUERIES],
+    pending: [bool; GPU_QUERIES],
+    current: usize,
+    last: Option<Duration>,
+}
+impl GpuTimer {
+    pub(crate) fn new(gl: &glow::Context) -> Option<Self> {
+        let version = gl.version();
+        let supported = (!version.is_embedded && (version.major, version.minor) >= (3, 3))
+            || gl.supported_extensions().contains("GL_ARB_timer_query")
+            || gl
+                .supported_extensions()
+                .contains("GL_EXT_disjoint_timer_query");
+        if !supported {
+            return None;
+        }
+        let mut queries = Vec::with_capacity(GPU_QUERIES);
+        for _ in 0..GPU_QUERIES {
+            queries.push(unsafe { gl.create_query().ok()? });
+        }
+        Some(Self {
+            queries: queries.try_into().ok()?,
+            pending: [false; GPU_QUERIES],
+            current: 0,
+            last: None,
+        })
+    }
+    pub(crate) fn begin(&mut self, gl: &glow::Context) {
+        let query = self.queries[self.current];
+        unsafe {
+            if self.pending[self.current] {
+                // The oldest query; wait for it rather than lose the slot.
+                let nanos = gl.get_query_parameter_u32(query, glow::QUERY_RESULT);
+                self.last = Some(Duration::from_nanos(nanos as u64));
+                self.pending[self.current] = false;
+            }
+            gl.begin_query(glow::TIME_ELAPSED, query);
+        }
+    }
+    pub(crate) fn end(&mut self, gl: &glow::Context) -> Option<Duration> {
+        unsafe {
+            gl.end_query(glow::TIME_ELAPSED);
+        }
+        self.pending[self.current] = true;
+        self.current = (self.current + 1) % GPU_QUERIES;
+        let oldest = self.current;
+        if self.pending[oldest] {
+            let query = self.queries[oldest];
+            unsafe {
+                if gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) != 0 {
+                    let nanos = gl.get_query_parameter_u32(query, glow::QUERY_RESULT);
+                    self.last = Some(Duration::from_nanos(nanos as u64));
+                    self.pending[oldest] = false;
+                }
+            }
+        }
+        self.last
+    }
+    pub(crate) fn destroy(&self, gl: &glow::Context) {
+        for query in self.queries {
+            unsafe { gl.delete_query(query) };
+        }
+    }
+}
@@ -0,0 +1,596 @@
+//! TTF text drawn from a glyph atlas, either in screen pixels or in the
+//! local XY plane of a world transform.
+use crate::prelude::*;
+use crate::stats::FrameStats;
+use std::collections::HashMap;
+use std::mem::offset_of;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const ATLAS_SIZE: usize = 1024;
+/// Pixel size glyphs are rasterized at for world-space text, scaled afterwards.
+const WORLD_PX: f32 = 48.0;
+
+const TEXT_VS: &str = r#"#version 330
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec2 aTexCoords;
+layout (location = 2) in vec4 aColor;
+uniform mat4 transform;
+out vec2 uv;
+out vec4 color;
+void main()
+{
+    gl_Position = transform * vec4(aPos, 1.0);
+    uv = aTexCoords;
+    color = aColor;
+}
+"#;
+const TEXT_FS: &str = r#"#version 330
+in vec2 uv;
+in vec4 color;
+uniform sampler2D atlas;
+out vec4 FragColor;
+void main()
+{
+    FragColor = vec4(color.rgb, color.a * texture(atlas, uv).r);
+}
+"#;
+
+static NEXT_FONT_ID: AtomicU32 = AtomicU32::new(0);
+
+pub struct Font {
+    id: u32,
+    font: fontdue::Font,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PositionedGlyph {
+    pub character: char,
+    /// Pen position on the baseline, y growing downwards.
+    pub position: Vec2,
+}
+
+impl Font {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let font = fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default())
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
+            font,
+        })
+    }
+    pub fn line_height(&self, px: f32) -> f32 {
+        self.font
+            .horizontal_line_metrics(px)
+            .map_or(px * 1.2, |m| m.new_line_size)
+    }
+    fn ascent(&self, px: f32) -> f32 {
+        self.font
+            .horizontal_line_metrics(px)
+            .map_or(px, |m| m.ascent)
+    }
+    fn advance(&self, character: char, next: Option<char>, px: f32) -> f32 {
+        let kern = next
+            .and_then(|next| self.font.horizontal_kern(character, next, px))
+            .unwrap_or(0.0);
+        self.font.metrics(character, px).advance_width + kern
+    }
+    fn word_width(&self, word: &str, px: f32) -> f32 {
+        let mut chars = word.chars().peekable();
+        let mut width = 0.0;
+        while let Some(c) = chars.next() {
+            width += self.advance(c, chars.peek().copied(), px);
+        }
+        width
+    }
+    /// Places each character of `text`, breaking on `\n` and, when given,
+    /// before words that would cross `max_width`.
+    pub fn layout(&self, text: &str, px: f32, max_width: Option<f32>) -> Vec<PositionedGlyph> {
+        let line_height = self.line_height(px);
+        let space = self.font.metrics(' ', px).advance_width;
+        let mut glyphs = vec![];
+        let mut pen = Vec2::new(0.0, self.ascent(px));
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                pen = Vec2::new(0.0, pen.y + line_height);
+            }
+            for (j, word) in line.split(' ').enumerate() {
+                if j > 0 {
+                    pen.x += space;
+                }
+                let width = self.word_width(word, px);
+                if max_width.is_some_and(|max| pen.x > 0.0 && pen.x + width > max) {
+                    pen = Vec2::new(0.0, pen.y + line_height);
+                }
+                let mut chars = word.chars().peekable();
+                while let Some(character) = chars.next() {
+                    glyphs.push(PositionedGlyph {
+                        character,
+                        position: pen,
+                    });
+                    pen.x += self.advance(character, chars.peek().copied(), px);
+                }
+            }
+        }
+        glyphs
+    }
+    /// Width and height of the laid out text.
+    pub fn measure(&self, text: &str, px: f32, max_width: Option<f32>) -> Vec2 {
+        let glyphs = self.layout(text, px, max_width);
+        let width = glyphs
+            .iter()
+            .map(|g| g.position.x + self.font.metrics(g.character, px).advance_width)
+            .fold(0.0, f32::max);
+        let lines = glyphs.last().map_or(0.0, |g| {
+            (g.position.y - self.ascent(px)) / self.line_height(px) + 1.0
+        });
+        Vec2::new(width, lines * self.line_height(px))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct AtlasGlyph {
+    /// Offset of the bitmap's top-left corner from the pen, y down.
+    offset: Vec2,
+    size: Vec2,
+    uv_min: Vec2,
+    uv_max: Vec2,
+}
+
+/// Single-channel atlas filled shelf by shelf as new glyphs show up.
+struct GlyphAtlas {
+    pixels: Vec<u8>,
+    /// Keyed by whole rasterized pixel size; `None` for glyphs without a
+    /// bitmap, like spaces.
+    glyphs: HashMap<(u32, char, u32), Option<AtlasGlyph>>,
+    cursor: (usize, usize),
+    shelf_height: usize,
+    dirty: bool,
+    /// A glyph didn't fit this frame; the atlas is rebuilt after drawing.
+    full: bool,
+    warned: bool,
+}
+impl GlyphAtlas {
+    fn new() -> Self {
+        Self {
+            pixels: vec![0; ATLAS_SIZE * ATLAS_SIZE],
+            glyphs: HashMap::new(),
+            cursor: (0, 0),
+            shelf_height: 0,
+            dirty: true,
+            full: false,
+            warned: false,
+        }
+    }
+    /// The glyph's placement in units of `px`, rasterized at the nearest
+    /// whole pixel size so scaled text doesn't fill the atlas with near
+    /// duplicates.
+    fn glyph(&mut self, font: &Font, character: char, px: f32) -> Option<AtlasGlyph> {
+        let raster_px = px.round().max(1.0);
+        let key = (font.id, character, raster_px as u32);
+        let glyph = match self.glyphs.get(&key) {
+            Some(glyph) => *glyph,
+            None => {
+                let glyph = self.rasterize(font, character, raster_px).ok()?;
+                self.glyphs.insert(key, glyph);
+                glyph
+            }
+        }?;
+        let scale = px / raster_px;
+        Some(AtlasGlyph {
+            offset: glyph.offset * scale,
+            size: glyph.size * scale,
+            ..glyph
+        })
+    }
+    /// `Err` when the atlas has no room left, which isn't cached.
+    fn rasterize(
+        &mut self,
+        font: &Font,
+        character: char,
+        px: f32,
+    ) -> Result<Option<AtlasGlyph>, ()> {
+        let (metrics, bitmap) = font.font.rasterize(character, px);
+        if metrics.width == 0 || metrics.height == 0 {
+            return Ok(None);
+        }
+        let Some((x, y)) = self.insert(metrics.width, metrics.height, &bitmap) else {
+            if !self.warned {
+                ::log::warn!("glyph atlas is full; rebuilding it after this frame");
+                self.warned = true;
+            }
+            self.full = true;
+            return Err(());
+        };
+        let texel = 1.0 / ATLAS_SIZE as f32;
+        Ok(Some(AtlasGlyph {
+            offset: Vec2::new(
+                metrics.xmin as f32,
+                -(metrics.ymin as f32 + metrics.height as f32),
+            ),
+            size: Vec2::new(metrics.width as f32, metrics.height as f32),
+            uv_min: Vec2::new(x as f32, y as f32) * texel,
+            uv_max: Vec2::new((x + metrics.width) as f32, (y + metrics.height) as f32) * texel,
+        }))
+    }
+    /// Drops every glyph so the ones still in use are packed again from
+    /// scratch. Quads already queued point into the old layout, so this only
+    /// runs once they're drawn.
+    fn clear(&mut self) {
+        self.pixels.fill(0);
+        self.glyphs.clear();
+        self.cursor = (0, 0);
+        self.shelf_height = 0;
+        self.dirty = true;
+        self.full = false;
+    }
+    fn insert(&mut self, width: usize, height: usize, bitmap: &[u8]) -> Option<(usize, usize)> {
+        if self.cursor.0 + width + 1 > ATLAS_SIZE {
+            self.cursor = (0, self.cursor.1 + self.shelf_height + 1);
+            self.shelf_height = 0;
+        }
+        if self.cursor.1 + height + 1 > ATLAS_SIZE {
+            return None;
+        }
+        let (x, y) = self.cursor;
+        for row in 0..height {
+            let start = (y + row) * ATLAS_SIZE + x;
+            self.pixels[start..start + width]
+                .copy_from_slice(&bitmap[row * width..(row + 1) * width]);
+        }
+        self.cursor.0 += width + 1;
+        self.shelf_height = self.shelf_height.max(height);
+        self.dirty = true;
+        Some((x, y))
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct TextVertex {
+    position: Vec3,
+    uv: Vec2,
+    color: Vec4,
+}
+
+pub(crate) struct TextRenderer {
+    program: NativeProgram,
+    vao: NativeVertexArray,
+    vbo: NativeBuffer,
+    texture: NativeTexture,
+    atlas: GlyphAtlas,
+    screen: Vec<TextVertex>,
+    world: Vec<TextVertex>,
+}
+
+impl TextRenderer {
+    pub(crate) fn new(gl: &glow::Context) -> Self {
+        unsafe {
+            let program = crate::shader::Shader::create_program(gl, TEXT_VS, TEXT_FS)
+                .expect("built-in text shader");
+            let vao = gl.create_vertex_array().unwrap();
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_vertex_array(Some(vao));
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            let stride = size_of::<TextVertex>() as i32;
+            gl.enable_vertex_attrib_array(0);
+            gl.vertex_attrib_pointer_f32(
+                0,
+                3,
+                glow::FLOAT,
+                false,
+                stride,
+                offset_of!(TextVertex, position) as i32,
+            );
+            gl.enable_vertex_attrib_array(1);
+            gl.vertex_attrib_pointer_f32(
+                1,
+                2,
+                glow::FLOAT,
+                false,
+                stride,
+                offset_of!(TextVertex, uv) as i32,
+            );
+            gl.enable_vertex_attrib_array(2);
+            gl.vertex_attrib_pointer_f32(
+                2,
+                4,
+                glow::FLOAT,
+                false,
+                stride,
+                offset_of!(TextVertex, color) as i32,
+            );
+            gl.bind_vertex_array(None);
+
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            for (parameter, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+                (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            Self {
+                program,
+                vao,
+                vbo,
+                texture,
+                atlas: GlyphAtlas::new(),
+                screen: vec![],
+                world: vec![],
+            }
+        }
+    }
+    fn push_quads(
+        atlas: &mut GlyphAtlas,
+        out: &mut Vec<TextVertex>,
+        font: &Font,
+        glyphs: &[PositionedGlyph],
+        px: f32,
+        color: Vec4,
+        place: impl Fn(Vec2) -> Vec3,
+    ) {
+        for positioned in glyphs {
+            let Some(glyph) = atlas.glyph(font, positioned.character, px) else {
+                continue;
+            };
+            let min = positioned.position + glyph.offset;
+            let max = min + glyph.size;
+            let corners = [
+                (Vec2::new(min.x, min.y), glyph.uv_min),
+                (
+                    Vec2::new(max.x, min.y),
+                    Vec2::new(glyph.uv_max.x, glyph.uv_min.y),
+                ),
+                (Vec2::new(max.x, max.y), glyph.uv_max),
+                (
+                    Vec2::new(min.x, max.y),
+                    Vec2::new(glyph.uv_min.x, glyph.uv_max.y),
+                ),
+            ];
+            for i in [0, 1, 2, 2, 3, 0] {
+                let (corner, uv) = corners[i];
+                out.push(TextVertex {
+                    position: place(corner),
+                    uv,
+                    color,
+                });
+            }
+        }
+    }
+    pub(crate) fn queue_screen(
+        &mut self,
+        font: &Font,
+        text: &str,
+        position: Vec2,
+        px: f32,
+        max_width: Option<f32>,
+        color: Vec4,
+    ) {
+        let glyphs = font.layout(text, px, max_width);
+        Self::push_quads(
+            &mut self.atlas,
+            &mut self.screen,
+            font,
+            &glyphs,
+            px,
+            color,
+            |p| (position + p).extend(0.0),
+        );
+    }
+    /// `size` is the em height in local units of `transform`; the text starts
+    /// at its origin and reads along +X with +Y up.
+    pub(crate) fn queue_world(
+        &mut self,
+        font: &Font,
+        text: &str,
+        transform: &Mat4,
+        size: f32,
+        color: Vec4,
+    ) {
+        let glyphs = font.layout(text, WORLD_PX, None);
+        let scale = size / WORLD_PX;
+        Self::push_quads(
+            &mut self.atlas,
+            &mut self.world,
+            font,
+            &glyphs,
+            WORLD_PX,
+            color,
+            |p| transform.transform_point3(Vec3::new(p.x * scale, -p.y * scale, 0.0)),
+        );
+    }
+    fn draw_batch(
+        &self,
+        gl: &glow::Context,
+        vertices: &[TextVertex],
+        transform: &Mat4,
+        stats: &mut FrameStats,
+    ) {
+        if vertices.is_empty() {
+            return;
+        }
+        stats.draw(vertices.len() as u64 / 3);
+        unsafe {
+            let vertices_u8: &[u8] = core::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(vertices),
+            );
+            let location = gl.get_uniform_location(self.program, "transform");
+            gl.uniform_matrix_4_f32_slice(location.as_ref(), false, &transform.to_cols_array());
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices_u8, glow::STREAM_DRAW);
+            gl.draw_arrays(glow::TRIANGLES, 0, vertices.len() as i32);
+        }
+    }
+    pub(crate) fn flush(
+        &mut self,
+        gl: &glow::Context,
+        view_projection: &Mat4,
+        screen: Vec2,
+        stats: &mut FrameStats,
+    ) {
+        if self.screen.is_empty() && self.world.is_empty() {
+            return;
+        }
+        stats.program_binds += 1;
+        // Texture and vertex array.
+        stats.state_changes += 2;
+        unsafe {
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+            if self.atlas.dirty {
+                gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    glow::R8 as i32,
+                    ATLAS_SIZE as i32,
+                    ATLAS_SIZE as i32,
+                    0,
+                    glow::RED,
+                    glow::UNSIGNED_BYTE,
+                    glow::PixelUnpackData::Slice(Some(&self.atlas.pixels)),
+                );
+                gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+                self.atlas.dirty = false;
+            }
+            gl.use_program(Some(self.program));
+            let atlas = gl.get_uniform_location(self.program, "atlas");
+            gl.uniform_1_i32(atlas.as_ref(), 0);
+            gl.bind_vertex_array(Some(self.vao));
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
+        }
+        // Starts from the default state the render queue leaves behind. World
+        // text is depth tested against the scene, but glyph quads shouldn't
+        // hide each other.
+        let world = RenderState::transparent();
+        let screen_state = RenderState {
+            depth_test: None,
+            ..world
+        };
+        let default = RenderState::default();
+        stats.state_changes += world.apply(gl, Some(&default));
+        self.draw_batch(gl, &self.world, view_projection, stats);
+        let ortho = Mat4::orthographic_rh_gl(0.0, screen.x, screen.y, 0.0, -1.0, 1.0);
+        stats.state_changes += screen_state.apply(gl, Some(&world));
+        self.draw_batch(gl, &self.screen, &ortho, stats);
+        stats.state_changes += default.apply(gl, Some(&screen_state));
+        unsafe {
+            gl.bind_vertex_array(None);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.use_program(None);
+        }
+        self.screen.clear();
+        self.world.clear();
+        if self.atlas.full {
+            self.atlas.clear();
+        }
+    }
+    pub(crate) fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.program);
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+            gl.delete_texture(self.texture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> Font {
+        Font::from_bytes(include_bytes!("../../assets/fonts/Hack-Regular.ttf")).unwrap()
+    }
+
+    /// Hack is monospaced, so every character advances the same.
+    fn advance(font: &Font, px: f32) -> f32 {
+        font.font.metrics('a', px).advance_width
+    }
+
+    fn positions(glyphs: &[PositionedGlyph]) -> Vec<(char, Vec2)> {
+        glyphs.iter().map(|g| (g.character, g.position)).collect()
+    }
+
+    #[test]
+    fn layout_places_characters_along_the_baseline() {
+        let font = font();
+        let (advance, ascent) = (advance(&font, 20.0), font.ascent(20.0));
+        let glyphs = font.layout("ab c", 20.0, None);
+        assert_eq!(
+            positions(&glyphs),
+            [
+                ('a', Vec2::new(0.0, ascent)),
+                ('b', Vec2::new(advance, ascent)),
+                ('c', Vec2::new(advance * 3.0, ascent)),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_breaks_on_newlines() {
+        let font = font();
+        let (ascent, line_height) = (font.ascent(20.0), font.line_height(20.0));
+        let glyphs = font.layout("a\n\nb", 20.0, None);
+        assert_eq!(
+            positions(&glyphs),
+            [
+                ('a', Vec2::new(0.0, ascent)),
+                ('b', Vec2::new(0.0, ascent + line_height * 2.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_wraps_words_past_max_width() {
+        let font = font();
+        let advance = advance(&font, 20.0);
+        let glyphs = font.layout("aa bb cc", 20.0, Some(advance * 5.5));
+        let lines: Vec<_> = glyphs.iter().map(|g| g.position.y).collect();
+        assert_eq!(lines[0], lines[3]);
+        assert!(lines[4] > lines[3]);
+        assert_eq!(glyphs[4].position.x, 0.0);
+        // A word wider than the limit still starts its own line unbroken.
+        let glyphs = font.layout("abcdef", 20.0, Some(advance));
+        assert!(glyphs.iter().all(|g| g.position.y == glyphs[0].position.y));
+    }
+
+    #[test]
+    fn measure_covers_the_widest_line_and_every_line() {
+        let font = font();
+        let (advance, line_height) = (advance(&font, 20.0), font.line_height(20.0));
+        let size = font.measure("ab\ncde", 20.0, None);
+        assert!((size.x - advance * 3.0).abs() < 1e-3);
+        assert!((size.y - line_height * 2.0).abs() < 1e-3);
+        assert_eq!(font.measure("", 20.0, None), Vec2::ZERO);
+    }
+
+    #[test]
+    fn full_atlas_is_not_cached_and_rebuilds() {
+        let font = font();
+        let mut atlas = GlyphAtlas::new();
+        assert!(atlas.glyph(&font, ' ', 20.0).is_none());
+        assert!(!atlas.full);
+        atlas.cursor = (0, ATLAS_SIZE - 2);
+        assert!(atlas.glyph(&font, 'A', 20.0).is_none());
+        assert!(atlas.full);
+        assert!(!atlas.glyphs.contains_key(&(font.id, 'A', 20)));
+        atlas.clear();
+        assert!(atlas.glyph(&font, 'A', 20.0).is_some());
+    }
+
+    #[test]
+    fn nearby_sizes_share_a_glyph() {
+        let font = font();
+        let mut atlas = GlyphAtlas::new();
+        let a = atlas.glyph(&font, 'A', 20.0).unwrap();
+        let b = atlas.glyph(&font, 'A', 20.2).unwrap();
+        assert_eq!(atlas.glyphs.len(), 1);
+        assert_eq!(a.uv_min, b.uv_min);
+        assert!((b.size.y / a.size.y - 20.2 / 20.0).abs() < 1e-5);
+    }
+}
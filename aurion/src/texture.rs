@@ -0,0 +1,132 @@
+//! 2D textures, uploaded from RGBA8 pixels or decoded from PNG files.
+use crate::prelude::*;
+use std::sync::Arc;
+
+/// Decoded RGBA8 pixels, rows from the top, not yet uploaded.
+#[derive(Clone, Debug, Default)]
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+impl TextureData {
+    /// Decodes a PNG file's contents.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let image = image::load_from_memory(bytes)
+            .map_err(|e| format!("invalid image: {e}"))?
+            .to_rgba8();
+        let (width, height) = image.dimensions();
+        Ok(Self {
+            width,
+            height,
+            pixels: image.into_raw(),
+        })
+    }
+}
+
+pub struct Texture {
+    texture: NativeTexture,
+    pub width: u32,
+    pub height: u32,
+    gl: Arc<glow::Context>,
+}
+impl Texture {
+    /// Uploads `pixels`, rows from the top, with mipmaps and repeat wrapping.
+    pub fn from_rgba8(
+        graphics: &Graphics,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<Self, String> {
+        check_rgba8(width, height, pixels)?;
+        let gl = &graphics.gl;
+        unsafe {
+            let texture = gl.create_texture().expect("Cannot create texture");
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA8 as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                PixelUnpackData::Slice(Some(pixels)),
+            );
+            gl.generate_mipmap(glow::TEXTURE_2D);
+            for (parameter, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::LINEAR_MIPMAP_LINEAR),
+                (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                (glow::TEXTURE_WRAP_S, glow::REPEAT),
+                (glow::TEXTURE_WRAP_T, glow::REPEAT),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            Ok(Self {
+                texture,
+                width,
+                height,
+                gl: Arc::clone(gl),
+            })
+        }
+    }
+    pub fn new(graphics: &Graphics, data: &TextureData) -> Result<Self, String> {
+        Self::from_rgba8(graphics, data.width, data.height, &data.pixels)
+    }
+    /// Decodes a PNG file's contents.
+    pub fn from_image_bytes(graphics: &Graphics, bytes: &[u8]) -> Result<Self, String> {
+        Self::new(graphics, &TextureData::decode(bytes)?)
+    }
+    pub(crate) fn raw(&self) -> NativeTexture {
+        self.texture
+    }
+}
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_texture(self.texture);
+        }
+    }
+}
+
+/// GL reads `width * height * 4` bytes whatever the slice's length, so a short
+/// one would be read past its end.
+fn check_rgba8(width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    let size = i32::try_from(width.max(height))
+        .ok()
+        .and_then(|_| {
+            (width as usize)
+                .checked_mul(height as usize)?
+                .checked_mul(4)
+        })
+        .ok_or_else(|| format!("{width}x{height} texture is too large"))?;
+    if pixels.len() < size {
+        return Err(format!(
+            "{} bytes is too few for {width}x{height} RGBA8 pixels",
+            pixels.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgba8_needs_four_bytes_per_pixel() {
+        assert!(check_rgba8(2, 3, &[0; 24]).is_ok());
+        assert!(check_rgba8(2, 3, &[0; 32]).is_ok());
+        assert!(check_rgba8(2, 3, &[0; 23]).is_err());
+        assert!(check_rgba8(1, 1, &[]).is_err());
+        assert!(check_rgba8(0, 0, &[]).is_ok());
+    }
+
+    #[test]
+    fn rgba8_rejects_sizes_gl_cant_take() {
+        assert!(check_rgba8(u32::MAX, 1, &[0; 4]).is_err());
+        assert!(check_rgba8(1 << 31, 1 << 31, &[0; 4]).is_err());
+    }
+}
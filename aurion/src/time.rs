@@ -0,0 +1,56 @@
+pub struct Time {
+    delta: f32,
+    unscaled_delta: f32,
+    elapsed: f64,
+    unscaled_elapsed: f64,
+    frame_count: u64,
+    fixed_delta: f32,
+    time_scale: f32,
+}
+impl Time {
+    pub fn new(fixed_delta: f32) -> Self {
+        Self {
+            delta: 0.0,
+            unscaled_delta: 0.0,
+            elapsed: 0.0,
+            unscaled_elapsed: 0.0,
+            frame_count: 0,
+            fixed_delta,
+            time_scale: 1.0,
+        }
+    }
+    pub(crate) fn advance(&mut self, unscaled_delta: f32) {
+        self.unscaled_delta = unscaled_delta;
+        self.delta = unscaled_delta * self.time_scale;
+        self.unscaled_elapsed += unscaled_delta as f64;
+        self.elapsed += self.delta as f64;
+        self.frame_count += 1;
+    }
+    /// Seconds since the last frame, multiplied by the time scale.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+    pub fn unscaled_delta(&self) -> f32 {
+        self.unscaled_delta
+    }
+    /// Scaled seconds since the loop started.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+    pub fn unscaled_elapsed(&self) -> f64 {
+        self.unscaled_elapsed
+    }
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+    pub fn fixed_delta(&self) -> f32 {
+        self.fixed_delta
+    }
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+    /// 0 freezes scaled time, values below 1 give slow motion.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+}
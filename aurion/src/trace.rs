@@ -0,0 +1,36 @@
+//! Spans around engine phases, compiled in with the `trace` feature and
+//! exported as Chrome trace JSON that Perfetto or `chrome://tracing` can open.
+#[cfg(feature = "trace")]
+pub use tracing_chrome::FlushGuard;
+
+/// Opens a span for the rest of the enclosing scope. Without the `trace`
+/// feature it expands to a zero-sized guard and its fields aren't evaluated.
+#[cfg(feature = "trace")]
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        tracing::info_span!($($arg)*).entered()
+    };
+}
+#[cfg(not(feature = "trace"))]
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        $crate::trace::NoSpan
+    };
+}
+pub(crate) use trace_span;
+
+#[cfg(not(feature = "trace"))]
+pub(crate) struct NoSpan;
+
+/// Installs a global subscriber that records every span into `path`. The file
+/// is complete once the returned guard is dropped, so keep it alive in `main`.
+#[cfg(feature = "trace")]
+pub fn chrome_trace(path: impl AsRef<std::path::Path>) -> FlushGuard {
+    use tracing_subscriber::prelude::*;
+    let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+        .file(path)
+        .include_args(true)
+        .build();
+    tracing_subscriber::registry().with(layer).init();
+    guard
+}
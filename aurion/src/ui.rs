@@ -0,0 +1,229 @@
+//! egui overlay drawn on top of the scene. The pass spans a whole frame, from
+//! `Graphics::begin_frame` to `Graphics::end_frame`, so behaviors can build
+//! panels from `update` through `ui::context()`.
+use crate::prelude::*;
+use egui::{Modifiers, PointerButton, Pos2, RawInput};
+use sdl2::clipboard::ClipboardUtil;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+thread_local! {
+    static CURRENT: RefCell<Option<egui::Context>> = const { RefCell::new(None) };
+}
+
+/// The UI context of the frame in flight, if any.
+pub fn context() -> Option<egui::Context> {
+    CURRENT.with_borrow(|ctx| ctx.clone())
+}
+
+pub struct UiOverlay {
+    ctx: egui::Context,
+    painter: egui_glow::Painter,
+    clipboard: ClipboardUtil,
+    input: RawInput,
+    modifiers: Modifiers,
+    pointer: Pos2,
+    start: Instant,
+    /// Window size in points, which is what SDL reports mouse positions in.
+    window_size: (u32, u32),
+    drawable_size: (u32, u32),
+}
+
+impl UiOverlay {
+    pub(crate) fn new(gl: Arc<glow::Context>, window: &sdl2::video::Window) -> Self {
+        let painter = egui_glow::Painter::new(gl, "", None, true).expect("Can't create UI painter");
+        Self {
+            ctx: egui::Context::default(),
+            painter,
+            clipboard: window.subsystem().clipboard(),
+            input: RawInput::default(),
+            modifiers: Modifiers::default(),
+            pointer: Pos2::ZERO,
+            start: Instant::now(),
+            window_size: window.size(),
+            drawable_size: window.drawable_size(),
+        }
+    }
+    pub fn context(&self) -> &egui::Context {
+        &self.ctx
+    }
+    /// Whether the overlay is using the mouse, so the game should ignore it.
+    pub fn wants_pointer(&self) -> bool {
+        self.ctx.wants_pointer_input()
+    }
+    pub fn wants_keyboard(&self) -> bool {
+        self.ctx.wants_keyboard_input()
+    }
+    fn pixels_per_point(&self) -> f32 {
+        self.drawable_size.0 as f32 / self.window_size.0.max(1) as f32
+    }
+    pub(crate) fn handle_event(&mut self, event: &Event) {
+        let event = match *event {
+            Event::MouseMotion { x, y, .. } => {
+                self.pointer = Pos2::new(x as f32, y as f32);
+                egui::Event::PointerMoved(self.pointer)
+            }
+            Event::MouseButtonDown { mouse_btn, .. } | Event::MouseButtonUp { mouse_btn, .. } => {
+                let Some(button) = pointer_button(mouse_btn) else {
+                    return;
+                };
+                egui::Event::PointerButton {
+                    pos: self.pointer,
+                    button,
+                    pressed: matches!(event, Event::MouseButtonDown { .. }),
+                    modifiers: self.modifiers,
+                }
+            }
+            Event::MouseWheel {
+                precise_x,
+                precise_y,
+                ..
+            } => egui::Event::MouseWheel {
+                unit: egui::MouseWheelUnit::Line,
+                delta: egui::vec2(precise_x, precise_y),
+                modifiers: self.modifiers,
+            },
+            Event::TextInput { ref text, .. } => egui::Event::Text(text.clone()),
+            Event::KeyDown {
+                keycode,
+                keymod,
+                repeat,
+                ..
+            }
+            | Event::KeyUp {
+                keycode,
+                keymod,
+                repeat,
+                ..
+            } => {
+                self.modifiers = modifiers(keymod);
+                self.input.modifiers = self.modifiers;
+                let Some(key) = keycode.and_then(key) else {
+                    return;
+                };
+                let pressed = matches!(event, Event::KeyDown { .. });
+                if pressed && self.modifiers.command {
+                    match key {
+                        egui::Key::C => self.input.events.push(egui::Event::Copy),
+                        egui::Key::X => self.input.events.push(egui::Event::Cut),
+                        egui::Key::V => {
+                            if let Ok(text) = self.clipboard.clipboard_text() {
+                                self.input.events.push(egui::Event::Paste(text));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                egui::Event::Key {
+                    key,
+                    physical_key: None,
+                    pressed,
+                    repeat,
+                    modifiers: self.modifiers,
+                }
+            }
+            Event::Window {
+                win_event: WindowEvent::Leave,
+                ..
+            } => egui::Event::PointerGone,
+            Event::Window {
+                win_event: WindowEvent::FocusGained,
+                ..
+            } => egui::Event::WindowFocused(true),
+            Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            } => egui::Event::WindowFocused(false),
+            _ => return,
+        };
+        self.input.events.push(event);
+    }
+    pub(crate) fn begin(&mut self) {
+        let mut input = std::mem::take(&mut self.input);
+        input.time = Some(self.start.elapsed().as_secs_f64());
+        input.screen_rect = Some(egui::Rect::from_min_size(
+            Pos2::ZERO,
+            egui::vec2(self.window_size.0 as f32, self.window_size.1 as f32),
+        ));
+        input.modifiers = self.modifiers;
+        self.ctx.set_pixels_per_point(self.pixels_per_point());
+        self.ctx.begin_pass(input);
+        CURRENT.with_borrow_mut(|ctx| *ctx = Some(self.ctx.clone()));
+    }
+    pub(crate) fn end(&mut self, window: &sdl2::video::Window) {
+        CURRENT.with_borrow_mut(|ctx| *ctx = None);
+        let output = self.ctx.end_pass();
+        let primitives = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+        self.painter.paint_and_update_textures(
+            [self.drawable_size.0, self.drawable_size.1],
+            output.pixels_per_point,
+            &primitives,
+            &output.textures_delta,
+        );
+        for command in output.platform_output.commands {
+            if let egui::OutputCommand::CopyText(text) = command {
+                let _ = self.clipboard.set_clipboard_text(&text);
+            }
+        }
+        self.window_size = window.size();
+        self.drawable_size = window.drawable_size();
+    }
+    pub(crate) fn destroy(&mut self) {
+        self.painter.destroy();
+    }
+}
+
+fn pointer_button(button: MouseButton) -> Option<PointerButton> {
+    match button {
+        MouseButton::Left => Some(PointerButton::Primary),
+        MouseButton::Right => Some(PointerButton::Secondary),
+        MouseButton::Middle => Some(PointerButton::Middle),
+        MouseButton::X1 => Some(PointerButton::Extra1),
+        MouseButton::X2 => Some(PointerButton::Extra2),
+        MouseButton::Unknown => None,
+    }
+}
+
+fn modifiers(keymod: Mod) -> Modifiers {
+    let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+    let mac_cmd = cfg!(target_os = "macos") && keymod.intersects(Mod::LGUIMOD | Mod::RGUIMOD);
+    Modifiers {
+        alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        ctrl,
+        shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+        mac_cmd,
+        command: if cfg!(target_os = "macos") {
+            mac_cmd
+        } else {
+            ctrl
+        },
+    }
+}
+
+fn key(keycode: Keycode) -> Option<egui::Key> {
+    use egui::Key;
+    Some(match keycode {
+        Keycode::Left => Key::ArrowLeft,
+        Keycode::Right => Key::ArrowRight,
+        Keycode::Up => Key::ArrowUp,
+        Keycode::Down => Key::ArrowDown,
+        Keycode::Escape => Key::Escape,
+        Keycode::Tab => Key::Tab,
+        Keycode::Backspace => Key::Backspace,
+        Keycode::Return | Keycode::KpEnter => Key::Enter,
+        Keycode::Space => Key::Space,
+        Keycode::Insert => Key::Insert,
+        Keycode::Delete => Key::Delete,
+        Keycode::Home => Key::Home,
+        Keycode::End => Key::End,
+        Keycode::PageUp => Key::PageUp,
+        Keycode::PageDown => Key::PageDown,
+        Keycode::Minus => Key::Minus,
+        Keycode::Equals => Key::Equals,
+        // Letters, digits and function keys share their names with egui.
+        _ => return Key::from_name(&keycode.name()),
+    })
+}
@@ -1,8 +1,37 @@
 use std::io::Read;
 
-pub fn read_file(path: &str) -> String {
-    let mut file = std::fs::File::open(path).expect("failed to open file");
+pub fn read_file(path: &str) -> Result<String, String> {
+    let mut file = std::fs::File::open(path).map_err(|e| format!("{path}: {e}"))?;
     let mut text = String::new();
-    file.read_to_string(&mut text).expect("Unable to read file");
-    text
+    file.read_to_string(&mut text)
+        .map_err(|e| format!("{path}: {e}"))?;
+    Ok(text)
+}
+
+/// Little-endian fields off the front of a slice, for binary file formats.
+pub(crate) struct ByteReader<'a>(pub &'a [u8]);
+impl<'a> ByteReader<'a> {
+    pub fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if count > self.0.len() {
+            return Err("unexpected end of file".to_string());
+        }
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(bytes)
+    }
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    pub fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
 }
@@ -0,0 +1,266 @@
+//! Vertex formats described at runtime, so meshes can carry whatever
+//! attributes they need. Each semantic has a fixed attribute location and
+//! name; `Shader` binds the names before linking, so shaders only have to
+//! declare e.g. `in vec3 aPos;`.
+use crate::prelude::*;
+use std::mem::offset_of;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Semantic {
+    Position,
+    Normal,
+    TexCoord0,
+    /// `xyz` along +U, bitangent sign in `w`.
+    Tangent,
+    Color,
+    TexCoord1,
+    /// Bone indices for skinning.
+    Joints,
+    Weights,
+}
+impl Semantic {
+    pub const ALL: [Semantic; 8] = [
+        Semantic::Position,
+        Semantic::Normal,
+        Semantic::TexCoord0,
+        Semantic::Tangent,
+        Semantic::Color,
+        Semantic::TexCoord1,
+        Semantic::Joints,
+        Semantic::Weights,
+    ];
+    pub fn location(self) -> u32 {
+        self as u32
+    }
+    /// The `in` variable shaders declare for this semantic.
+    pub fn attribute_name(self) -> &'static str {
+        match self {
+            Semantic::Position => "aPos",
+            Semantic::Normal => "aNormal",
+            Semantic::TexCoord0 => "aTexCoords",
+            Semantic::Tangent => "aTangent",
+            Semantic::Color => "aColor",
+            Semantic::TexCoord1 => "aTexCoords1",
+            Semantic::Joints => "aJoints",
+            Semantic::Weights => "aWeights",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VertexFormat {
+    Float32,
+    Float32x2,
+    Float32x3,
+    Float32x4,
+    Uint8x4,
+    Uint16x4,
+    Uint32x4,
+    Float16x2,
+    Float16x4,
+}
+impl VertexFormat {
+    pub fn components(self) -> i32 {
+        match self {
+            VertexFormat::Float32 => 1,
+            VertexFormat::Float32x2 | VertexFormat::Float16x2 => 2,
+            VertexFormat::Float32x3 => 3,
+            _ => 4,
+        }
+    }
+    pub fn size(self) -> u32 {
+        match self {
+            VertexFormat::Float32 | VertexFormat::Uint8x4 | VertexFormat::Float16x2 => 4,
+            VertexFormat::Float32x2 | VertexFormat::Uint16x4 | VertexFormat::Float16x4 => 8,
+            VertexFormat::Float32x3 => 12,
+            VertexFormat::Float32x4 | VertexFormat::Uint32x4 => 16,
+        }
+    }
+    fn gl_type(self) -> u32 {
+        match self {
+            VertexFormat::Float32
+            | VertexFormat::Float32x2
+            | VertexFormat::Float32x3
+            | VertexFormat::Float32x4 => glow::FLOAT,
+            VertexFormat::Float16x2 | VertexFormat::Float16x4 => glow::HALF_FLOAT,
+            VertexFormat::Uint8x4 => glow::UNSIGNED_BYTE,
+            VertexFormat::Uint16x4 => glow::UNSIGNED_SHORT,
+            VertexFormat::Uint32x4 => glow::UNSIGNED_INT,
+        }
+    }
+    fn is_integer(self) -> bool {
+        matches!(
+            self,
+            VertexFormat::Uint8x4 | VertexFormat::Uint16x4 | VertexFormat::Uint32x4
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VertexAttribute {
+    pub semantic: Semantic,
+    pub format: VertexFormat,
+    /// Byte offset inside a vertex.
+    pub offset: u32,
+    /// Integer formats read as `0..1` floats instead of integers, e.g. 8-bit
+    /// colors. Non-normalized integers need `ivec`/`uvec` shader inputs.
+    pub normalized: bool,
+}
+
+/// Interleaved attributes of one vertex buffer.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct VertexLayout {
+    pub attributes: Vec<VertexAttribute>,
+    pub stride: u32,
+}
+impl VertexLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Appends an attribute right after the previous one.
+    pub fn with(self, semantic: Semantic, format: VertexFormat) -> Self {
+        self.with_normalized(semantic, format, false)
+    }
+    pub fn with_normalized(
+        mut self,
+        semantic: Semantic,
+        format: VertexFormat,
+        normalized: bool,
+    ) -> Self {
+        self.attributes.push(VertexAttribute {
+            semantic,
+            format,
+            offset: self.stride,
+            normalized,
+        });
+        self.stride += format.size();
+        self
+    }
+    pub fn attribute(&self, semantic: Semantic) -> Option<&VertexAttribute> {
+        self.attributes.iter().find(|a| a.semantic == semantic)
+    }
+    /// Reads `Float32x3` positions out of interleaved `vertices`, e.g. for bounds.
+    pub fn positions<'a>(&self, vertices: &'a [u8]) -> impl Iterator<Item = Vec3> + Clone + 'a {
+        let position = self
+            .attribute(Semantic::Position)
+            .filter(|a| a.format == VertexFormat::Float32x3)
+            .map(|a| a.offset as usize);
+        let stride = (self.stride as usize).max(1);
+        vertices.chunks_exact(stride).filter_map(move |vertex| {
+            let bytes = vertex.get(position?..position? + 12)?;
+            let f = |i: usize| f32::from_ne_bytes(bytes[i..i + 4].try_into().unwrap());
+            Some(Vec3::new(f(0), f(4), f(8)))
+        })
+    }
+    /// Points the attributes at the buffer bound to `ARRAY_BUFFER` in the
+    /// bound vertex array.
+    pub(crate) fn apply(&self, gl: &glow::Context) {
+        for attribute in &self.attributes {
+            let location = attribute.semantic.location();
+            let format = attribute.format;
+            unsafe {
+                gl.enable_vertex_attrib_array(location);
+                if format.is_integer() && !attribute.normalized {
+                    gl.vertex_attrib_pointer_i32(
+                        location,
+                        format.components(),
+                        format.gl_type(),
+                        self.stride as i32,
+                        attribute.offset as i32,
+                    );
+                } else {
+                    gl.vertex_attrib_pointer_f32(
+                        location,
+                        format.components(),
+                        format.gl_type(),
+                        attribute.normalized,
+                        self.stride as i32,
+                        attribute.offset as i32,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// First of the four locations of the `aInstanceModel` matrix filled by
+/// instanced draws.
+pub(crate) const INSTANCE_MODEL_LOCATION: u32 = 8;
+
+/// A `#[repr(C)]` vertex struct that can be uploaded as is.
+///
+/// # Safety
+///
+/// `vertex_bytes` reads values of this type as plain bytes, so it must be
+/// `#[repr(C)]` (or otherwise have a fixed layout) with no padding, and only
+/// contain fields that are themselves plain data, like floats and integers.
+/// `layout()` must describe attributes inside `size_of::<Self>()`.
+pub unsafe trait VertexType: Copy {
+    fn layout() -> VertexLayout;
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+    /// Tangent along +U, with the bitangent's sign in `w`.
+    pub tangent: Vec4,
+}
+// Safety: `#[repr(C)]` of `f32` vectors, 48 bytes with no padding.
+const _: () = assert!(size_of::<Vertex>() == 48);
+unsafe impl VertexType for Vertex {
+    fn layout() -> VertexLayout {
+        let attribute = |semantic, format, offset| VertexAttribute {
+            semantic,
+            format,
+            offset: offset as u32,
+            normalized: false,
+        };
+        VertexLayout {
+            attributes: vec![
+                attribute(
+                    Semantic::Position,
+                    VertexFormat::Float32x3,
+                    offset_of!(Vertex, position),
+                ),
+                attribute(
+                    Semantic::Normal,
+                    VertexFormat::Float32x3,
+                    offset_of!(Vertex, normal),
+                ),
+                attribute(
+                    Semantic::TexCoord0,
+                    VertexFormat::Float32x2,
+                    offset_of!(Vertex, uv),
+                ),
+                attribute(
+                    Semantic::Tangent,
+                    VertexFormat::Float32x4,
+                    offset_of!(Vertex, tangent),
+                ),
+            ],
+            stride: size_of::<Vertex>() as u32,
+        }
+    }
+}
+
+/// Positions only, e.g. for depth-only or collision meshes.
+// Safety: three `f32`s with no padding.
+unsafe impl VertexType for Vec3 {
+    fn layout() -> VertexLayout {
+        VertexLayout::new().with(Semantic::Position, VertexFormat::Float32x3)
+    }
+}
+
+/// The raw bytes of `vertices`, for uploading.
+pub fn vertex_bytes<V: VertexType>(vertices: &[V]) -> &[u8] {
+    // Safety: `VertexType` guarantees `V` has no padding or pointers.
+    unsafe {
+        core::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            std::mem::size_of_val(vertices),
+        )
+    }
+}
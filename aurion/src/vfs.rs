@@ -0,0 +1,381 @@
+//! A virtual filesystem. Directories and archives are mounted at virtual
+//! paths such as `""` or `"user"`; reads go to the highest-priority mount that
+//! has the file, so a mod mounted above the game's assets overrides them file
+//! by file. Paths use forward slashes and can't leave their mount with `..`.
+use crate::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+/// Something files can be read from, like a directory or an archive.
+pub trait Mount: Send + Sync {
+    /// The file at `path`, relative to the mount, or `None` if it isn't here.
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String>;
+    fn exists(&self, path: &str) -> bool;
+    fn write(&self, path: &str, _bytes: &[u8]) -> Result<(), String> {
+        Err(format!("{path}: read-only"))
+    }
+    fn is_writable(&self) -> bool {
+        false
+    }
+    /// Where the file at `path` is on disk, if it's a plain file that can be
+    /// memory-mapped.
+    fn real_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+pub struct DirMount {
+    root: PathBuf,
+    writable: bool,
+}
+impl DirMount {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            writable: false,
+        }
+    }
+    /// Also takes `Vfs::write_file`, creating directories as needed.
+    pub fn writable(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            writable: true,
+        }
+    }
+}
+impl Mount for DirMount {
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        match std::fs::read(self.root.join(path)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("{path}: {e}")),
+        }
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.root.join(path).is_file()
+    }
+    fn write(&self, path: &str, bytes: &[u8]) -> Result<(), String> {
+        if !self.writable {
+            return Err(format!("{path}: read-only"));
+        }
+        let file = self.root.join(path);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("{path}: {e}"))?;
+        }
+        std::fs::write(file, bytes).map_err(|e| format!("{path}: {e}"))
+    }
+    fn is_writable(&self) -> bool {
+        self.writable
+    }
+    fn real_path(&self, path: &str) -> Option<PathBuf> {
+        Some(self.root.join(path)).filter(|file| file.is_file())
+    }
+}
+
+pub struct ZipMount {
+    archive: Mutex<zip::ZipArchive<std::fs::File>>,
+}
+impl ZipMount {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let archive = zip::ZipArchive::new(file).map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(Self {
+            archive: Mutex::new(archive),
+        })
+    }
+}
+impl Mount for ZipMount {
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = match archive.by_name(path) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(format!("{path}: {e}")),
+        };
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes)
+            .map_err(|e| format!("{path}: {e}"))?;
+        Ok(Some(bytes))
+    }
+    fn exists(&self, path: &str) -> bool {
+        self.archive.lock().unwrap().index_for_name(path).is_some()
+    }
+}
+
+struct MountPoint {
+    point: String,
+    priority: i32,
+    mount: Box<dyn Mount>,
+}
+impl MountPoint {
+    /// `path` relative to this mount, if it's under its mount point.
+    fn relative<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.point.is_empty() {
+            return Some(path);
+        }
+        path.strip_prefix(self.point.as_str())?.strip_prefix('/')
+    }
+}
+
+#[derive(Default)]
+pub struct Vfs {
+    /// Highest priority first; among equal priorities the latest mount first.
+    mounts: RwLock<Vec<MountPoint>>,
+}
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The game's assets read-only at the root, from `assets.pak` and the
+    /// `assets` directory, loose files winning. The user data directory for
+    /// `app` is writable under `user`, and its `mods` are mounted over the
+    /// assets.
+    pub fn with_defaults(app: &str) -> Self {
+        let vfs = Self::new();
+        if let Some(pak) = Self::near_exe("assets.pak")
+            .into_iter()
+            .find(|path| path.is_file())
+        {
+            match PakMount::open(&pak) {
+                Ok(pak) => vfs.mount("", pak, 0),
+                Err(e) => ::log::error!("cannot mount assets: {e}"),
+            }
+        }
+        match Self::assets_dir() {
+            Some(assets) => vfs.mount("", DirMount::new(assets), 0),
+            None => ::log::debug!("no assets directory found next to the executable"),
+        }
+        if let Some(user) = Self::user_dir(app) {
+            if let Err(e) = vfs.mount_mods(user.join("mods"), 10) {
+                ::log::warn!("cannot mount mods: {e}");
+            }
+            vfs.mount("user", DirMount::writable(user), 0);
+        }
+        vfs
+    }
+    /// The first `assets` directory next to the executable or up to two
+    /// levels above it, so `cargo run` finds the one in the crate root. Falls
+    /// back to `assets` in the working directory.
+    pub fn assets_dir() -> Option<PathBuf> {
+        Self::near_exe("assets")
+            .into_iter()
+            .find(|dir| dir.is_dir())
+    }
+    fn near_exe(name: &str) -> Vec<PathBuf> {
+        let exe = std::env::current_exe().ok();
+        let mut paths: Vec<PathBuf> = exe
+            .iter()
+            .flat_map(|exe| exe.ancestors().skip(1).take(3))
+            .map(|dir| dir.join(name))
+            .collect();
+        paths.push(PathBuf::from(name));
+        paths
+    }
+    /// Where `app` keeps saves and settings, e.g. `~/.local/share/app`.
+    pub fn user_dir(app: &str) -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join(app))
+    }
+    /// Mounts `mount` at `point`. Higher priorities are searched first.
+    pub fn mount(&self, point: &str, mount: impl Mount + 'static, priority: i32) {
+        let mut mounts = self.mounts.write().unwrap();
+        let index = mounts
+            .iter()
+            .position(|m| m.priority <= priority)
+            .unwrap_or(mounts.len());
+        mounts.insert(
+            index,
+            MountPoint {
+                point: normalize(point).unwrap_or_default(),
+                priority,
+                mount: Box::new(mount),
+            },
+        );
+    }
+    /// Removes everything mounted at `point`.
+    pub fn unmount(&self, point: &str) {
+        let point = normalize(point).unwrap_or_default();
+        self.mounts.write().unwrap().retain(|m| m.point != point);
+    }
+    /// Mounts each directory, `.zip` and `.pak` file in `dir` at the root, in name
+    /// order from `priority` up, so later mods override earlier ones. A
+    /// missing `dir` mounts nothing. Returns how many were mounted.
+    pub fn mount_mods(&self, dir: impl AsRef<Path>, priority: i32) -> Result<usize, String> {
+        let dir = dir.as_ref();
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Ok(0);
+        };
+        let mut paths: Vec<PathBuf> = entries.filter_map(|e| Some(e.ok()?.path())).collect();
+        paths.sort();
+        let mut count = 0;
+        for path in paths {
+            if path.is_dir() {
+                self.mount("", DirMount::new(&path), priority + count);
+            } else if path.extension().is_some_and(|e| e == "zip") {
+                self.mount("", ZipMount::open(&path)?, priority + count);
+            } else if path.extension().is_some_and(|e| e == "pak") {
+                self.mount("", PakMount::open(&path)?, priority + count);
+            } else {
+                continue;
+            }
+            ::log::info!("mounted mod {}", path.display());
+            count += 1;
+        }
+        Ok(count as usize)
+    }
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, String> {
+        let path = normalize(path)?;
+        for mount in self.mounts.read().unwrap().iter() {
+            if let Some(relative) = mount.relative(&path) {
+                if let Some(bytes) = mount.mount.read(relative)? {
+                    return Ok(bytes);
+                }
+            }
+        }
+        Err(format!("{path}: not found"))
+    }
+    pub fn read_to_string(&self, path: &str) -> Result<String, String> {
+        String::from_utf8(self.read_file(path)?).map_err(|_| format!("{path}: not valid UTF-8"))
+    }
+    pub fn exists(&self, path: &str) -> bool {
+        let Ok(path) = normalize(path) else {
+            return false;
+        };
+        self.mounts.read().unwrap().iter().any(|mount| {
+            mount
+                .relative(&path)
+                .is_some_and(|relative| mount.mount.exists(relative))
+        })
+    }
+    /// Where the file `read_file` would read is on disk, if it's a plain file
+    /// rather than inside an archive.
+    pub fn real_path(&self, path: &str) -> Option<PathBuf> {
+        let path = normalize(path).ok()?;
+        let mounts = self.mounts.read().unwrap();
+        let (mount, relative) = mounts.iter().find_map(|mount| {
+            let relative = mount.relative(&path)?;
+            mount.mount.exists(relative).then_some((mount, relative))
+        })?;
+        mount.mount.real_path(relative)
+    }
+    /// Writes to the highest-priority writable mount `path` is under.
+    pub fn write_file(&self, path: &str, bytes: &[u8]) -> Result<(), String> {
+        let path = normalize(path)?;
+        let mounts = self.mounts.read().unwrap();
+        let (mount, relative) = mounts
+            .iter()
+            .filter(|mount| mount.mount.is_writable())
+            .find_map(|mount| Some((mount, mount.relative(&path)?)))
+            .ok_or_else(|| format!("{path}: no writable mount"))?;
+        mount.mount.write(relative, bytes)
+    }
+}
+
+/// Forward slashes with `.` and empty parts removed; `..` may not climb out
+/// of the root.
+pub(crate) fn normalize(path: &str) -> Result<String, String> {
+    let mut parts: Vec<&str> = vec![];
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts
+                    .pop()
+                    .ok_or_else(|| format!("{path}: outside the filesystem"))?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemoryMount {
+        files: Mutex<HashMap<String, Vec<u8>>>,
+    }
+    impl MemoryMount {
+        fn with(files: &[(&str, &str)]) -> Self {
+            let files = files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.as_bytes().to_vec()))
+                .collect();
+            Self {
+                files: Mutex::new(files),
+            }
+        }
+    }
+    impl Mount for MemoryMount {
+        fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+            Ok(self.files.lock().unwrap().get(path).cloned())
+        }
+        fn exists(&self, path: &str) -> bool {
+            self.files.lock().unwrap().contains_key(path)
+        }
+        fn write(&self, path: &str, bytes: &[u8]) -> Result<(), String> {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(path.to_string(), bytes.to_vec());
+            Ok(())
+        }
+        fn is_writable(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn normalize_drops_empty_and_dot_segments() {
+        assert_eq!(normalize("a/b/c").unwrap(), "a/b/c");
+        assert_eq!(normalize("/a//b/./c/").unwrap(), "a/b/c");
+        assert_eq!(normalize("").unwrap(), "");
+        assert_eq!(normalize("./").unwrap(), "");
+    }
+
+    #[test]
+    fn normalize_accepts_backslashes() {
+        assert_eq!(normalize("a\\b\\c").unwrap(), "a/b/c");
+        assert_eq!(normalize("a\\b/..\\c").unwrap(), "a/c");
+    }
+
+    #[test]
+    fn normalize_resolves_parents_inside_the_root() {
+        assert_eq!(normalize("a/b/../c").unwrap(), "a/c");
+        assert_eq!(normalize("a/..").unwrap(), "");
+        assert!(normalize("..").is_err());
+        assert!(normalize("../a").is_err());
+        assert!(normalize("a/../../b").is_err());
+        assert!(normalize("a\\..\\..\\b").is_err());
+    }
+
+    #[test]
+    fn higher_priorities_and_later_mounts_win() {
+        let vfs = Vfs::new();
+        vfs.mount("", MemoryMount::with(&[("a", "base"), ("b", "base")]), 0);
+        vfs.mount("", MemoryMount::with(&[("a", "mod")]), 10);
+        vfs.mount("", MemoryMount::with(&[("b", "patch")]), 0);
+        assert_eq!(vfs.read_to_string("a").unwrap(), "mod");
+        assert_eq!(vfs.read_to_string("b").unwrap(), "patch");
+        assert!(vfs.read_file("c").is_err());
+    }
+
+    #[test]
+    fn mount_points_scope_paths() {
+        let vfs = Vfs::new();
+        vfs.mount("/user/", MemoryMount::with(&[("save", "1")]), 0);
+        assert_eq!(vfs.read_to_string("user/save").unwrap(), "1");
+        assert_eq!(vfs.read_to_string("user\\.\\save").unwrap(), "1");
+        assert!(!vfs.exists("save"));
+        assert!(!vfs.exists("username/save"));
+        assert!(vfs.read_file("user/../../save").is_err());
+        vfs.write_file("user/new", b"2").unwrap();
+        assert_eq!(vfs.read_to_string("user/new").unwrap(), "2");
+        assert!(vfs.write_file("elsewhere", b"3").is_err());
+        vfs.unmount("user");
+        assert!(!vfs.exists("user/save"));
+    }
+}
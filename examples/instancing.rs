@@ -0,0 +1,112 @@
+//! Draws a 100x100 grid of cubes, first one draw call per cube and then with
+//! instancing, and logs the average frame cost of each path once both have
+//! been measured.
+//!
+//!     cargo run --release --example instancing
+use aurion::prelude::*;
+use std::time::Duration;
+
+const GRID: i32 = 100;
+const WARMUP_FRAMES: u32 = 60;
+const MEASURED_FRAMES: u32 = 300;
+
+#[derive(Default)]
+struct Totals {
+    frames: u32,
+    render: Duration,
+    gpu: Duration,
+    draw_calls: u64,
+}
+impl Totals {
+    fn add(&mut self, stats: &FrameStats) {
+        self.frames += 1;
+        self.render += stats.cpu_time(Phase::Render);
+        self.gpu += stats.gpu_time.unwrap_or_default();
+        self.draw_calls += stats.draw_calls as u64;
+    }
+    fn report(&self, path: &str) {
+        let frames = self.frames.max(1);
+        aurion::log::info!(
+            "{path}: {:.3} ms render CPU, {:.3} ms GPU, {} draw calls per frame",
+            (self.render / frames).as_secs_f64() * 1000.0,
+            (self.gpu / frames).as_secs_f64() * 1000.0,
+            self.draw_calls / frames as u64,
+        );
+    }
+}
+
+struct Benchmark {
+    mesh: GpuMesh,
+    material: Material,
+    transforms: Vec<Mat4>,
+    frame: u32,
+    per_object: Totals,
+    instanced: Totals,
+}
+
+impl Game for Benchmark {
+    fn init(&mut self, graphics: &Graphics) {
+        let mut camera = graphics.camera();
+        camera.position = Vec3::new(0.0, GRID as f32 * 1.2, GRID as f32 * 1.2);
+        camera.far = GRID as f32 * 5.0;
+        graphics.set_camera(camera);
+        graphics.set_instancing(false);
+    }
+    fn update(&mut self, _time: &mut Time) {}
+    fn draw(&mut self, graphics: &Graphics) {
+        const CYCLE: u32 = WARMUP_FRAMES + MEASURED_FRAMES;
+        if self.frame < 2 * CYCLE {
+            // Stats of the previous frame, which was drawn with the current setting.
+            let stats = graphics.stats();
+            if self.frame % CYCLE > WARMUP_FRAMES {
+                if graphics.instancing() {
+                    self.instanced.add(&stats);
+                } else {
+                    self.per_object.add(&stats);
+                }
+            }
+            self.frame += 1;
+            if self.frame == CYCLE {
+                graphics.set_instancing(true);
+            } else if self.frame == 2 * CYCLE {
+                self.per_object.report("per-object");
+                self.instanced.report("instanced");
+            }
+        }
+        for transform in &self.transforms {
+            graphics.submit(&self.mesh, &self.material, transform);
+        }
+    }
+}
+
+fn main() {
+    aurion::logging::init();
+    let mut app = App::builder("instancing benchmark", 1280, 720)
+        .vsync(false)
+        .build()
+        .expect("Can't create the application window");
+    let graphics = Graphics::new(&app);
+    let assets = Assets::new(Vfs::with_defaults("aurion-instancing"));
+    let Ok(shader) = assets.load::<Shader>(&graphics, "shader") else {
+        std::process::exit(1);
+    };
+    let mut material = Material::new(shader);
+    material.set("tint", UniformValue::Vec4(Vec4::ONE));
+    let half = GRID as f32 / 2.0;
+    let transforms = (0..GRID * GRID)
+        .map(|i| {
+            let (x, z) = ((i % GRID) as f32 - half, (i / GRID) as f32 - half);
+            Mat4::from_translation(Vec3::new(x * 2.0, 0.0, z * 2.0))
+                * Mat4::from_scale(Vec3::splat(0.5))
+        })
+        .collect();
+    let mut benchmark = Benchmark {
+        mesh: graphics.create_cube(),
+        material,
+        transforms,
+        frame: 0,
+        per_object: Totals::default(),
+        instanced: Totals::default(),
+    };
+    app.run(&graphics, &mut benchmark, RunConfig::default());
+}
@@ -1,46 +1,149 @@
 mod rotation;
 
-use aurion::prelude::event::Event;
 use crate::rotation::Rotation;
+use aurion::prelude::event::Event;
+use aurion::prelude::keyboard::Keycode;
 use aurion::prelude::*;
 
+struct Cubes {
+    world: GameObject,
+    cube: GameObject,
+    cube2: GameObject,
+    inspector: SceneInspector,
+    font: Font,
+    fps: f32,
+    show_stats: bool,
+    console: LogConsole,
+    assets: Assets,
+}
+
+impl Game for Cubes {
+    fn init(&mut self, graphics: &Graphics) {
+        GameObjectNode::init(&self.world, graphics);
+    }
+    fn event(&mut self, event: &Event, _control: &mut Control) {
+        if let Event::KeyDown {
+            keycode: Some(key),
+            repeat: false,
+            ..
+        } = event
+        {
+            match *key {
+                Keycode::F1 => self.inspector.toggle(),
+                Keycode::F2 => self.show_stats = !self.show_stats,
+                Keycode::F3 => self.console.toggle(),
+                _ => {}
+            }
+        }
+    }
+    fn update(&mut self, time: &mut Time) {
+        GameObjectNode::update(&self.world, time);
+        self.fps = 1.0 / time.unscaled_delta().max(f32::EPSILON);
+        if let Some(ctx) = ui::context() {
+            self.inspector.show(&ctx, &self.world);
+            self.console.show(&ctx);
+        }
+    }
+    fn draw(&mut self, graphics: &Graphics) {
+        self.assets.update(graphics);
+        graphics.set_stats_overlay(self.show_stats);
+        GameObjectNode::draw(&self.world, graphics);
+        GameObjectNode::draw_axes(&self.world, 1.0);
+        for node in [&self.cube, &self.cube2] {
+            let label =
+                node.get_world_transform() * Mat4::from_translation(Vec3::new(-0.5, 0.8, 0.0));
+            graphics.draw_text_world(&self.font, &node.get_name(), &label, 0.3, Vec4::ONE);
+            if let Some(bounds) = node.get_world_bounds() {
+                debug::aabb(bounds.min, bounds.max, debug::YELLOW);
+            }
+        }
+        let fps = format!("{:.0} fps", self.fps);
+        graphics.draw_text(&self.font, &fps, Vec2::new(10.0, 10.0), 24.0, Vec4::ONE);
+    }
+}
 
 fn main() {
-    let vs = aurion::prelude::read_file("assets/shader.vs");
-    let fs = aurion::prelude::read_file("assets/shader.fs");
+    aurion::logging::init();
+    #[cfg(feature = "trace")]
+    let _trace = aurion::trace::chrome_trace("trace.json");
 
-    let mut app = App::new("my first app", 1920, 1080);
-    let mut graphics = Graphics::new(&app);
+    let mut app = App::builder("my first app", 1920, 1080)
+        .msaa_samples(4)
+        .build()
+        .or_else(|e| {
+            aurion::log::warn!("{e}, retrying without MSAA");
+            App::new("my first app", 1920, 1080)
+        })
+        .expect("Can't create the application window");
+    let graphics = Graphics::new(&app);
+    let assets = Assets::new(Vfs::with_defaults("cubes"));
+    let font = match assets.vfs().read_file("fonts/Hack-Regular.ttf") {
+        Ok(font) => Font::from_bytes(&font).expect("invalid font"),
+        Err(e) => {
+            aurion::log::error!("cannot load font: {e}");
+            std::process::exit(1);
+        }
+    };
+    assets.set_placeholder(
+        Texture::from_rgba8(&graphics, 1, 1, &[255; 4]).expect("placeholder texture"),
+    );
 
     let world = GameObjectNode::new("world");
     let mut cube = GameObjectNode::new("cube");
     let cube2 = GameObjectNode::new("cube2");
+    let sphere = GameObjectNode::new("sphere");
 
     GameObjectNode::add_child(&world, &cube);
     GameObjectNode::add_child(&cube, &cube2);
+    GameObjectNode::add_child(&world, &sphere);
     cube.add_behavior(Rotation::new().unwrap());
     cube.set_local_transform(Mat4::from_translation(Vec3::new(0.0, 0.0, 0.0)));
     cube2.set_local_transform(Mat4::from_translation(Vec3::new(2.0, 0.0, 0.0)));
+    sphere.set_local_transform(Mat4::from_translation(Vec3::new(-2.5, 0.0, 0.0)));
 
-    GameObjectNode::init(&world, &mut graphics);
-    let mesh = graphics.create_cube();
-    let shader = Shader::new(&graphics, &vs, &fs);
-    shader.use_program();
-    let mut last_time = Instant::now();
+    let shader = match assets.load::<Shader>(&graphics, "shader") {
+        Ok(shader) => shader,
+        Err(_) => std::process::exit(1),
+    };
+    let mut material = Material::new(shader.clone());
+    material.set("tint", UniformValue::Vec4(Vec4::ONE));
+    let material = assets.insert("materials/default", material);
+    let mut tinted = Material::new(shader);
+    tinted.set("tint", UniformValue::Vec4(Vec4::new(0.6, 0.8, 1.0, 0.6)));
+    tinted.render_state = RenderState::transparent();
+    let tinted = assets.insert("materials/tinted", tinted);
 
-    'render: loop {
-        for event in app.event_loop().poll_iter() {
-            if let Event::Quit { .. } = event {
-                break 'render;
-            }
-        }
-        let current_time = Instant::now();
-        let delta_time = current_time.duration_since(last_time);
-        last_time = current_time;
-        GameObjectNode::update(&world, delta_time.as_secs_f32());
-        graphics.begin_frame();
-        graphics.draw_mesh(&cube2.get_world_transform(), &shader, &mesh);
-        graphics.draw_mesh(&cube.get_world_transform(), &shader, &mesh);
-        graphics.end_frame(&app.window());
+    let cube_mesh = assets.insert("meshes/cube", graphics.create_cube());
+    for (node, material) in [(&cube, &material), (&cube2, &tinted)] {
+        node.set_mesh(Some(cube_mesh.handle()));
+        node.set_material(Some(material.handle()));
     }
+    let detailed = primitives::icosphere(0.5, 4);
+    let triangles = detailed.triangle_count();
+    let levels = [(1, 0.4), (4, 0.15), (16, 0.03)]
+        .into_iter()
+        .map(|(ratio, screen_size)| {
+            let data = processing::simplify(&detailed, triangles / ratio, 0.05);
+            let name = format!("meshes/sphere_lod{ratio}");
+            LodLevel {
+                mesh: assets.insert(&name, GpuMesh::new(&graphics, &data)).into(),
+                screen_size,
+            }
+        })
+        .collect();
+    sphere.set_lod_group(Some(LodGroup::new(levels).with_fade(0.2)));
+    sphere.set_material(Some(material.into()));
+
+    let mut cubes = Cubes {
+        world,
+        cube,
+        cube2,
+        inspector: SceneInspector::new(),
+        font,
+        fps: 0.0,
+        show_stats: false,
+        console: LogConsole::new(),
+        assets,
+    };
+    app.run(&graphics, &mut cubes, RunConfig::default());
 }
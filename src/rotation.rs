@@ -4,11 +4,15 @@ use std::rc::Rc;
 
 pub struct Rotation {
     rotation: f32,
+    speed: f32,
 }
 
 impl Rotation {
     pub fn new() -> Option<MonoBehavior> {
-        Some(Rc::new(RefCell::new(Rotation { rotation: 0.0 })))
+        Some(Rc::new(RefCell::new(Rotation {
+            rotation: 0.0,
+            speed: 1.0,
+        })))
     }
 }
 impl Behavior for Rotation {
@@ -16,9 +20,8 @@ impl Behavior for Rotation {
         self.rotation = 0.0;
     }
 
-    fn update(&mut self, this: GameObject, delta_time: f32) {
-        println!("{}", this.get_name());
-        self.rotation += delta_time;
+    fn update(&mut self, this: GameObject, time: &Time) {
+        self.rotation += time.delta() * self.speed;
         let (scale, rotation, translation) =
             this.get_local_transform().to_scale_rotation_translation();
         let rotation = Quat::from_rotation_y(self.rotation);
@@ -28,4 +31,13 @@ impl Behavior for Rotation {
             translation,
         ));
     }
+    fn inspect(&mut self) -> Option<&mut dyn Inspect> {
+        Some(self)
+    }
+}
+impl Inspect for Rotation {
+    fn inspect(&mut self, inspector: &mut dyn Inspector) {
+        inspector.f32_range("speed", &mut self.speed, 0.0..=5.0);
+        inspector.f32("rotation", &mut self.rotation);
+    }
 }